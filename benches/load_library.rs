@@ -0,0 +1,135 @@
+//! Measures how long loading one playlist from a large iTunes library takes, and how much memory
+//! it needs.
+//!
+//! A synthetic library is written to the temp directory first. The size of the library and the
+//! memory ceiling can be changed with environment variables:
+//!
+//! ```bash
+//! ISPOT_BENCH_TRACKS=200000 ISPOT_BENCH_MAX_RSS_MB=64 cargo bench --bench load_library
+//! ```
+//!
+//! Peak memory is read from `/proc/self/status`, so the ceiling is only checked on Linux.
+use ispot::itunes::stream;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+
+const DEFAULT_TRACKS: usize = 200_000;
+const DEFAULT_MAX_RSS_MB: u64 = 64;
+const PLAYLIST_TRACKS: usize = 100;
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn write_library(path: &Path, tracks: usize) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<plist version="1.0">"#)?;
+    writeln!(out, "<dict>")?;
+    writeln!(out, "\t<key>Major Version</key><integer>1</integer>")?;
+    writeln!(out, "\t<key>Tracks</key>")?;
+    writeln!(out, "\t<dict>")?;
+    for id in 1..=tracks {
+        writeln!(out, "\t\t<key>{}</key>", id)?;
+        writeln!(out, "\t\t<dict>")?;
+        writeln!(out, "\t\t\t<key>Track ID</key><integer>{}</integer>", id)?;
+        writeln!(out, "\t\t\t<key>Name</key><string>Track {}</string>", id)?;
+        writeln!(
+            out,
+            "\t\t\t<key>Artist</key><string>Artist {}</string>",
+            id % 5000
+        )?;
+        writeln!(
+            out,
+            "\t\t\t<key>Album</key><string>Album {}</string>",
+            id % 20000
+        )?;
+        writeln!(out, "\t\t\t<key>Kind</key><string>MPEG audio file</string>")?;
+        writeln!(out, "\t\t\t<key>Total Time</key><integer>215000</integer>")?;
+        writeln!(
+            out,
+            "\t\t\t<key>Date Added</key><date>2019-10-01T12:30:00Z</date>"
+        )?;
+        writeln!(
+            out,
+            "\t\t\t<key>Location</key><string>file:///Users/me/Music/iTunes/Track%20{}.mp3</string>",
+            id
+        )?;
+        writeln!(out, "\t\t</dict>")?;
+    }
+    writeln!(out, "\t</dict>")?;
+    writeln!(out, "\t<key>Playlists</key>")?;
+    writeln!(out, "\t<array>")?;
+    writeln!(out, "\t\t<dict>")?;
+    writeln!(out, "\t\t\t<key>Name</key><string>Favourites</string>")?;
+    writeln!(out, "\t\t\t<key>Playlist Items</key>")?;
+    writeln!(out, "\t\t\t<array>")?;
+    for id in (1..=tracks).step_by((tracks / PLAYLIST_TRACKS).max(1)) {
+        writeln!(
+            out,
+            "\t\t\t\t<dict><key>Track ID</key><integer>{}</integer></dict>",
+            id
+        )?;
+    }
+    writeln!(out, "\t\t\t</array>")?;
+    writeln!(out, "\t\t</dict>")?;
+    writeln!(out, "\t</array>")?;
+    writeln!(out, "</dict>")?;
+    writeln!(out, "</plist>")?;
+    out.flush()
+}
+
+/// Peak resident set size of this process in MiB.
+fn peak_rss_mb() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb / 1024)
+}
+
+fn main() {
+    let tracks = env_or("ISPOT_BENCH_TRACKS", DEFAULT_TRACKS);
+    let max_rss_mb = env_or("ISPOT_BENCH_MAX_RSS_MB", DEFAULT_MAX_RSS_MB);
+    let path = env::temp_dir().join(format!("ispot-bench-{}.xml", process::id()));
+
+    write_library(&path, tracks).expect("failed to write the benchmark library");
+    let size_mb = fs::metadata(&path)
+        .map(|m| m.len() / 1024 / 1024)
+        .unwrap_or(0);
+
+    let start = Instant::now();
+    let library = stream::load_library(
+        path.to_str().unwrap(),
+        |_, playlist| playlist.name == "Favourites",
+        |_| (),
+    );
+    let elapsed = start.elapsed();
+    fs::remove_file(&path).ok();
+
+    let library = library.expect("failed to load the benchmark library");
+    println!(
+        "loaded {} of {} tracks from a {} MiB library in {:.2?}",
+        library.tracks.len(),
+        tracks,
+        size_mb,
+        elapsed
+    );
+
+    match peak_rss_mb() {
+        Some(peak) => {
+            println!("peak memory: {} MiB (ceiling {} MiB)", peak, max_rss_mb);
+            if peak > max_rss_mb {
+                eprintln!("peak memory exceeds the ceiling");
+                process::exit(1);
+            }
+        }
+        None => println!("peak memory: not available on this platform"),
+    }
+}
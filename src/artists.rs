@@ -0,0 +1,181 @@
+//! Artists of a library, and finding them on Spotify by name.
+use crate::itunes::Track;
+use std::collections::HashMap;
+
+/// Names that stand for many artists rather than one, e.g. the album artist of compilations.
+const VARIOUS_ARTISTS: &[&str] = &["various artists", "various", "va"];
+/// Separators of featured artists, e.g. `Daft Punk feat. Pharrell Williams`.
+const FEATURING: &[&str] = &[" feat. ", " feat ", " ft. ", " featuring "];
+
+/// An artist of the library and how many tracks it appears on.
+#[derive(Debug, PartialEq)]
+pub struct LibraryArtist {
+    /// The name as first found in the library.
+    pub name: String,
+    pub tracks: usize,
+}
+
+/// Collect the artists and album artists of tracks, most tracks first.
+///
+/// Names that differ only in case or punctuation are one artist, featured artists are left out
+/// and so are compilation artists like `Various Artists`.
+pub fn library_artists<'a, I>(tracks: I) -> Vec<LibraryArtist>
+where
+    I: IntoIterator<Item = &'a Track>,
+{
+    let mut artists: Vec<LibraryArtist> = Vec::new();
+    let mut artist_indices: HashMap<String, usize> = HashMap::new();
+    for track in tracks {
+        let mut keys = Vec::with_capacity(2);
+        for name in track.artist.iter().chain(track.album_artist.iter()) {
+            let name = main_artist(name);
+            let key = normalize_artist(name);
+            if key.is_empty() || VARIOUS_ARTISTS.contains(&key.as_str()) || keys.contains(&key) {
+                continue;
+            }
+            let index = *artist_indices.entry(key.clone()).or_insert_with(|| {
+                artists.push(LibraryArtist {
+                    name: name.to_string(),
+                    tracks: 0,
+                });
+                artists.len() - 1
+            });
+            artists[index].tracks += 1;
+            keys.push(key);
+        }
+    }
+    // The sort is stable, so artists with as many tracks stay in library order.
+    artists.sort_by_key(|artist| std::cmp::Reverse(artist.tracks));
+    artists
+}
+
+/// The artist a track is credited to, without any featured artists.
+fn main_artist(name: &str) -> &str {
+    // ASCII lowercasing keeps byte offsets, so they can be used to cut the original name.
+    let lowercase = name.to_ascii_lowercase();
+    let end = FEATURING
+        .iter()
+        .filter_map(|separator| lowercase.find(separator))
+        .min()
+        .unwrap_or(name.len());
+    name[..end].trim()
+}
+
+/// Reduce an artist name to what Spotify and iTunes agree on, e.g. `the beatles` for
+/// `The Beatles` or `simon and garfunkel` for `Simon & Garfunkel`.
+pub fn normalize_artist(name: &str) -> String {
+    let lowercase = name.to_lowercase().replace('&', " and ");
+    let words: Vec<&str> = lowercase
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    match words.as_slice() {
+        ["the", rest @ ..] if !rest.is_empty() => rest.join(" "),
+        _ => words.join(" "),
+    }
+}
+
+/// How an artist name was resolved against the artists found on Spotify.
+#[derive(Debug, PartialEq)]
+pub enum Resolution {
+    /// Index of the only artist with the same name.
+    Found(usize),
+    /// Indices of several artists with the same name, most popular first.
+    Ambiguous(Vec<usize>),
+    NotFound,
+}
+
+/// Find the Spotify artist with the same name, given the names and popularity of the search
+/// results.
+///
+/// With `pick_popular` the most popular of several artists with the same name is taken.
+pub fn resolve_artist(name: &str, candidates: &[(&str, u32)], pick_popular: bool) -> Resolution {
+    let key = normalize_artist(name);
+    let mut same_name: Vec<usize> = candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, (candidate, _))| normalize_artist(candidate) == key)
+        .map(|(i, _)| i)
+        .collect();
+    same_name.sort_by_key(|&i| std::cmp::Reverse(candidates[i].1));
+    match same_name.as_slice() {
+        [] => Resolution::NotFound,
+        [found] => Resolution::Found(*found),
+        [most_popular, ..] if pick_popular => Resolution::Found(*most_popular),
+        _ => Resolution::Ambiguous(same_name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{library_artists, main_artist, normalize_artist, resolve_artist, Resolution};
+    use crate::itunes::{test_track, Track};
+
+    fn track(artist: &str, album_artist: Option<&str>) -> Track {
+        let mut entries = vec![("Name", "Song".into()), ("Artist", artist.into())];
+        if let Some(album_artist) = album_artist {
+            entries.push(("Album Artist", album_artist.into()));
+        }
+        test_track(&entries)
+    }
+
+    #[test]
+    fn test_normalize_artist() {
+        assert_eq!(normalize_artist("The Beatles"), "beatles");
+        assert_eq!(normalize_artist("Simon & Garfunkel"), "simon and garfunkel");
+        assert_eq!(normalize_artist("AC/DC"), "ac dc");
+        assert_eq!(normalize_artist("The The"), "the");
+    }
+
+    #[test]
+    fn test_main_artist() {
+        assert_eq!(
+            main_artist("Daft Punk feat. Pharrell Williams"),
+            "Daft Punk"
+        );
+        assert_eq!(main_artist("Röyksopp Featuring Robyn"), "Röyksopp");
+        assert_eq!(main_artist("Featurecast"), "Featurecast");
+    }
+
+    #[test]
+    fn test_library_artists() {
+        let tracks = [
+            track("Massive Attack", None),
+            track("Daft Punk feat. Pharrell Williams", Some("Daft Punk")),
+            track("Daft Punk", None),
+            track("The Verve", Some("Various Artists")),
+            track("massive attack", None),
+            track("Verve", None),
+        ];
+        let artists = library_artists(&tracks);
+        let artists: Vec<(&str, usize)> = artists
+            .iter()
+            .map(|artist| (artist.name.as_str(), artist.tracks))
+            .collect();
+        assert_eq!(
+            artists,
+            vec![("Massive Attack", 2), ("Daft Punk", 2), ("The Verve", 2)]
+        );
+    }
+
+    #[test]
+    fn test_resolve_artist() {
+        let candidates = [("Nirvana", 80), ("Nirvana", 30), ("Nirvana UK", 10)];
+        assert_eq!(
+            resolve_artist("Nirvana", &candidates, false),
+            Resolution::Ambiguous(vec![0, 1])
+        );
+        assert_eq!(
+            resolve_artist("Nirvana", &candidates, true),
+            Resolution::Found(0)
+        );
+        assert_eq!(
+            resolve_artist("nirvana uk", &candidates, false),
+            Resolution::Found(2)
+        );
+        assert_eq!(
+            resolve_artist("Nirvana (UK)", &[("Pearl Jam", 70)], false),
+            Resolution::NotFound
+        );
+    }
+}
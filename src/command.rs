@@ -1,137 +1,1475 @@
-use crate::error::ErrorKind;
+use crate::artists::{self, Resolution};
+use crate::cover::{self, CoverSource};
+use crate::dedupe::{self, DuplicateKey, Keep};
+use crate::error::{Error, ErrorKind};
+use crate::export;
+use crate::generate;
 use crate::itunes;
+use crate::journal::Journal;
+use crate::matching::{self, AlbumTrack, MatchMode};
 use crate::output;
+use crate::playlist::{self, NameValues, PlaylistSettings};
+use crate::progress::MatchProgress;
+use crate::source::{self, SourceTrack};
 use crate::spotify;
 use crate::util;
 use clap::ArgMatches;
 use rspotify::spotify::model::track::FullTrack;
-use std::process;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
 
-pub fn authenticate(spotify_client_id: &str, spotify_client_secret: &str) {
-    match spotify::authenticate(&spotify_client_id, &spotify_client_secret) {
-        Ok(_) => println!("Successfully authenticated to Spotify API"),
-        Err(error) => println!("{}", error),
-    }
+/// Number of albums found by a search whose track listings are compared with a source album.
+const ALBUM_EDITIONS: u32 = 5;
+/// Number of artists found by a search that are compared with a library artist.
+const ARTIST_CANDIDATES: u32 = 10;
+/// Number of album covers in a mosaic cover.
+const MOSAIC_ALBUMS: usize = 4;
+/// Separator between the folders of a single playlist file in its `{folder}` name placeholder.
+const FOLDER_SEPARATOR: &str = " / ";
+/// Most tracks Spotify removes from a playlist in one request.
+const MAX_REMOVALS: usize = 100;
+
+pub fn authenticate(spotify_client_id: &str, spotify_client_secret: &str) -> Result<(), Error> {
+    spotify::authenticate(spotify_client_id, spotify_client_secret)?;
+    println!("Successfully authenticated to Spotify API");
+    Ok(())
 }
 
 /// Create a Spotify playlist.
-pub fn create_playlist(spotify_client_id: &str, spotify_client_secret: &str, matches: &ArgMatches) {
+pub fn create_playlist(
+    spotify_client_id: &str,
+    spotify_client_secret: &str,
+    matches: &ArgMatches,
+) -> Result<(), Error> {
     let name = matches.value_of("name").unwrap();
-    let spotify_wrapper =
-        spotify::SpotifyWrapper::new(&spotify_client_id, &spotify_client_secret).unwrap();
-    let playlist = match spotify_wrapper.create_playlist(&name) {
-        Ok(p) => p,
-        Err(e) => {
-            eprintln!("{}", e);
-            process::exit(1);
+    let settings = playlist_settings(matches)?;
+    let description = settings.description(name, 0, 0)?;
+    let spotify_wrapper = spotify::SpotifyWrapper::new(spotify_client_id, spotify_client_secret)?;
+    let playlist =
+        spotify_wrapper.create_playlist(name, settings.visibility, description.as_deref())?;
+    if let Some(ref cover) = settings.cover {
+        // An empty playlist has no artwork or album covers to make a cover from.
+        if !upload_cover(&spotify_wrapper, &playlist.id, cover, &[], &[])? {
+            warn!("no cover image found for {}", name);
         }
-    };
+    }
     output::tabulate_playlist(&playlist);
+    Ok(())
+}
+
+/// Remove the duplicate tracks of a Spotify playlist, keeping the first of each.
+pub fn dedupe_playlist(
+    spotify_client_id: &str,
+    spotify_client_secret: &str,
+    matches: &ArgMatches,
+) -> Result<(), Error> {
+    let playlist_id = matches.value_of("playlist").unwrap();
+    let print_only: bool = matches.is_present("print-only");
+    let dedupe_by: DuplicateKey = matches.value_of("dedupe-by").unwrap().parse()?;
+
+    let spotify_wrapper = spotify::SpotifyWrapper::new(spotify_client_id, spotify_client_secret)?;
+    // The positions of the duplicates only hold for the version of the playlist they were found
+    // in. The same snapshot ID before and after the listing makes sure it is all from one version,
+    // and the removals name that version, so a change made meanwhile doesn't shift them: Spotify
+    // applies them to that version, or rejects them.
+    let snapshot_id = spotify_wrapper.playlist_snapshot_id(playlist_id)?;
+    let tracks = spotify_wrapper.playlist_tracks(playlist_id)?;
+    if spotify_wrapper.playlist_snapshot_id(playlist_id)? != snapshot_id {
+        return Err(Error::new(ErrorKind::PlaylistChanged(
+            playlist_id.to_string(),
+        )));
+    }
+    let duplicates = dedupe::find_duplicates(tracks.iter().map(|track| dedupe_by.of(track)));
+
+    if !duplicates.is_empty() {
+        output::tabulate_duplicates(&tracks, &duplicates);
+        if !print_only {
+            let uris: Vec<&str> = tracks.iter().map(|track| track.uri.as_str()).collect();
+            let mut snapshot_id = snapshot_id;
+            for occurrences in dedupe::plan_removals(&uris, &duplicates, MAX_REMOVALS) {
+                snapshot_id = spotify_wrapper
+                    .remove_playlist_occurrences(playlist_id, &occurrences, &snapshot_id)
+                    .map_err(|e| e.context("Failed to remove the duplicates"))?;
+            }
+        }
+    }
+    println!("duplicate tracks: {}", duplicates.len());
+    println!("total tracks: {}", tracks.len());
+    Ok(())
 }
 
 /// List Spotify playlists.
-pub fn list_playlists(spotify_client_id: &str, spotify_client_secret: &str) {
-    let spotify_wrapper =
-        spotify::SpotifyWrapper::new(&spotify_client_id, &spotify_client_secret).unwrap();
-    let playlists = match spotify_wrapper.list_playlists() {
-        Ok(p) => p,
-        Err(e) => {
-            eprintln!("{}", e);
-            process::exit(1);
+pub fn list_playlists(spotify_client_id: &str, spotify_client_secret: &str) -> Result<(), Error> {
+    let spotify_wrapper = spotify::SpotifyWrapper::new(spotify_client_id, spotify_client_secret)?;
+    let playlists = spotify_wrapper.list_playlists()?;
+    output::tabulate_playlists(&playlists);
+    Ok(())
+}
+
+pub fn match_library(
+    spotify_client_id: &str,
+    spotify_client_secret: &str,
+    matches: &ArgMatches,
+) -> Result<(), Error> {
+    let path_to_library = matches.value_of("library").unwrap();
+    let print_only: bool = matches.is_present("print-only");
+    let include_builtin: bool = matches.is_present("include-builtin");
+    let skip_smart: bool = matches.is_present("skip-smart");
+    let flatten_folders: bool = matches.is_present("flatten-folders");
+    let folder_separator = matches.value_of("folder-separator").unwrap();
+    let name_prefix = matches.value_of("name-prefix").unwrap_or("");
+    let selected_playlists: Option<Vec<&str>> =
+        matches.values_of("playlist").map(Iterator::collect);
+    let include_kinds = include_kinds(matches)?;
+    let match_mode: MatchMode = matches.value_of("match-by").unwrap().parse()?;
+    let workers = workers(matches)?;
+    let settings = playlist_settings(matches)?;
+    let format = match matches.value_of("format") {
+        Some(format) => format.parse()?,
+        None => source::Format::detect(path_to_library),
+    };
+    let mut journal = journal(matches)?;
+
+    let date = playlist::today();
+
+    if format != source::Format::Itunes {
+        let library = source::load_library(path_to_library, format, &Default::default())?;
+        let spotify_wrapper =
+            spotify::SpotifyWrapper::new(spotify_client_id, spotify_client_secret)?;
+        let profile = profile_name(&spotify_wrapper, &settings)?;
+        for (index, playlist) in library.playlists.iter().enumerate() {
+            let name = playlist.name.as_deref().unwrap_or_default();
+            if let Some(ref names) = selected_playlists {
+                if !names.contains(&name) {
+                    continue;
+                }
+            }
+            // Names can be missing or repeated, but the playlists keep their order in the file.
+            let key = format!("{}#{}", path_to_library, index + 1);
+            if journal.playlist(&key).done {
+                info!("{} was converted before, skipping it", name);
+                continue;
+            }
+            let matched_tracks = match_tracks(
+                &spotify_wrapper,
+                &playlist.tracks,
+                match_mode,
+                workers,
+                &mut journal,
+            )?;
+
+            println!("{}", name);
+            if !print_only && !matched_tracks.is_empty() {
+                let values = NameValues {
+                    source_name: name,
+                    folder: "",
+                    date: &date,
+                    count: matched_tracks.len(),
+                    profile: profile.as_deref(),
+                };
+                let spotify_playlist_name =
+                    format!("{}{}", name_prefix, settings.name(&values, name)?);
+                let origin = Origin {
+                    name,
+                    key: &key,
+                    files: &local_files(&playlist.tracks),
+                    total: playlist.tracks.len(),
+                };
+                create_playlist_with_tracks(
+                    &spotify_wrapper,
+                    &settings,
+                    &mut journal,
+                    &spotify_playlist_name,
+                    &origin,
+                    &matched_tracks,
+                )?;
+            }
+            println!("matched tracks: {}", matched_tracks.len());
+            println!("total tracks: {}", playlist.tracks.len());
+            output::print_track_warnings(&playlist.warnings);
+        }
+        output::print_track_warnings(&library.warnings);
+        return Ok(());
+    }
+
+    let is_selected = |library: &itunes::Playlist, playlist: &itunes::LibraryPlaylist| {
+        if playlist.folder
+            || (playlist.is_builtin() && !include_builtin)
+            || (playlist.smart && skip_smart)
+        {
+            return false;
+        }
+        match selected_playlists {
+            Some(ref names) => {
+                let qualified_name = library.qualified_name(playlist, folder_separator);
+                names.contains(&playlist.name.as_str()) || names.contains(&qualified_name.as_str())
+            }
+            None => true,
         }
     };
-    output::tabulate_playlists(&playlists);
+
+    let library = itunes::stream::load_library(path_to_library, is_selected, log_load_progress())?;
+    let spotify_wrapper = spotify::SpotifyWrapper::new(spotify_client_id, spotify_client_secret)?;
+    let profile = profile_name(&spotify_wrapper, &settings)?;
+    let playlist_key = |playlist: &itunes::LibraryPlaylist| match playlist.persistent_id {
+        Some(ref persistent_id) => persistent_id.clone(),
+        None => format!(
+            "{}#{}",
+            path_to_library,
+            library.qualified_name(playlist, folder_separator)
+        ),
+    };
+    let keys: Vec<String> = library
+        .playlists
+        .iter()
+        .filter(|playlist| is_selected(&library, playlist))
+        .map(playlist_key)
+        .collect();
+    journal.check_keys(keys.iter().map(String::as_str))?;
+
+    for playlist in &library.playlists {
+        if !is_selected(&library, playlist) {
+            continue;
+        }
+        let qualified_name = library.qualified_name(playlist, folder_separator);
+        let key = playlist_key(playlist);
+        if journal.playlist(&key).done {
+            info!("{} was converted before, skipping it", qualified_name);
+            continue;
+        }
+
+        let (tracks, skipped_tracks) =
+            partition_by_kind(library.playlist_tracks(playlist), &include_kinds);
+        let tracks: Vec<SourceTrack> = tracks.into_iter().map(SourceTrack::from).collect();
+        let matched_tracks =
+            match_tracks(&spotify_wrapper, &tracks, match_mode, workers, &mut journal)?;
+
+        println!("{}", qualified_name);
+        if !print_only && !matched_tracks.is_empty() {
+            let default_name = if flatten_folders {
+                &playlist.name
+            } else {
+                &qualified_name
+            };
+            let values = NameValues {
+                source_name: &playlist.name,
+                folder: &library.folder_path(playlist).join(folder_separator),
+                date: &date,
+                count: matched_tracks.len(),
+                profile: profile.as_deref(),
+            };
+            let spotify_playlist_name =
+                format!("{}{}", name_prefix, settings.name(&values, default_name)?);
+            let origin = Origin {
+                name: &qualified_name,
+                key: &key,
+                files: &local_files(&tracks),
+                total: playlist.track_ids.len(),
+            };
+            create_playlist_with_tracks(
+                &spotify_wrapper,
+                &settings,
+                &mut journal,
+                &spotify_playlist_name,
+                &origin,
+                &matched_tracks,
+            )?;
+        }
+        println!("matched tracks: {}", matched_tracks.len());
+        println!("total tracks: {}", playlist.track_ids.len());
+        output::print_skipped_kinds(&skipped_tracks);
+    }
+
+    output::print_track_warnings(&library.warnings);
+    Ok(())
+}
+
+/// Turn the loved tracks of an iTunes library into Liked Songs, and its star ratings into
+/// playlists like `★★★★★ from iTunes`.
+pub fn convert_ratings(
+    spotify_client_id: &str,
+    spotify_client_secret: &str,
+    matches: &ArgMatches,
+) -> Result<(), Error> {
+    let path_to_library = matches.value_of("library").unwrap();
+    let print_only: bool = matches.is_present("print-only");
+    let loved: bool = matches.is_present("loved");
+    let rating_playlists: bool = matches.is_present("rating-playlists");
+    let min_rating = min_rating(matches)?.unwrap_or(1);
+    let name_suffix = matches.value_of("name-suffix").unwrap();
+    let include_kinds = include_kinds(matches)?;
+    let match_mode: MatchMode = matches.value_of("match-by").unwrap().parse()?;
+    let workers = workers(matches)?;
+    let settings = playlist_settings(matches)?;
+
+    let library = load_all_tracks(path_to_library)?;
+    let (tracks, skipped_tracks) = partition_by_kind(library.tracks.values(), &include_kinds);
+    let tracks: Vec<&itunes::Track> = tracks
+        .into_iter()
+        .filter(|track| {
+            (loved && track.loved) || (rating_playlists && has_min_rating(track, Some(min_rating)))
+        })
+        .collect();
+
+    let spotify_wrapper = spotify::SpotifyWrapper::new(spotify_client_id, spotify_client_secret)?;
+    let source_tracks: Vec<SourceTrack> = tracks.iter().map(|&track| track.into()).collect();
+    let matched_tracks = match_each_track(
+        &spotify_wrapper,
+        &source_tracks,
+        match_mode,
+        workers,
+        &mut Journal::default(),
+    )?;
+    let matched_where = |keep: &dyn Fn(&itunes::Track) -> bool| {
+        let mut kept = Vec::new();
+        let mut matched = Vec::new();
+        for ((track, source_track), matched_track) in
+            tracks.iter().zip(&source_tracks).zip(&matched_tracks)
+        {
+            if keep(track) {
+                kept.push(source_track.clone());
+                matched.extend(matched_track.clone());
+            }
+        }
+        (matched, kept)
+    };
+
+    if loved {
+        let (matched, loved_tracks) = matched_where(&|track| track.loved);
+        println!("Loved");
+        if !print_only && !matched.is_empty() {
+            let saved = save_to_library(&spotify_wrapper, &matched, false)?;
+            println!("saved tracks: {}", saved.tracks);
+            println!("already saved tracks: {}", saved.already_saved_tracks);
+        }
+        println!("matched tracks: {}", matched.len());
+        println!("total tracks: {}", loved_tracks.len());
+    }
+
+    if rating_playlists {
+        for stars in (min_rating..=5).rev() {
+            let (matched, rated_tracks) = matched_where(&|track| track.stars() == Some(stars));
+            if rated_tracks.is_empty() {
+                continue;
+            }
+            let name = format!("{}{}", "★".repeat(usize::from(stars)), name_suffix);
+            println!("{}", name);
+            if !print_only && !matched.is_empty() {
+                let origin = Origin {
+                    name: &name,
+                    key: &name,
+                    files: &local_files(&rated_tracks),
+                    total: rated_tracks.len(),
+                };
+                create_playlist_with_tracks(
+                    &spotify_wrapper,
+                    &settings,
+                    &mut Journal::default(),
+                    &name,
+                    &origin,
+                    &matched,
+                )?;
+            }
+            println!("matched tracks: {}", matched.len());
+            println!("total tracks: {}", rated_tracks.len());
+        }
+    }
+
+    output::print_skipped_kinds(&skipped_tracks);
+    output::print_track_warnings(&library.warnings);
+    Ok(())
+}
+
+/// Create playlists like `Top 100 most played` from the play statistics of an iTunes library.
+///
+/// The limit counts the tracks found on Spotify: tracks that aren't found are passed over for the
+/// next ones in line.
+pub fn generate(
+    spotify_client_id: &str,
+    spotify_client_secret: &str,
+    matches: &ArgMatches,
+) -> Result<(), Error> {
+    let path_to_library = matches.value_of("library").unwrap();
+    let print_only: bool = matches.is_present("print-only");
+    let rules: Vec<generate::Rule> = matches
+        .values_of("rule")
+        .unwrap()
+        .map(str::parse)
+        .collect::<Result<_, _>>()?;
+    let limit: Option<usize> = match matches.value_of("limit") {
+        Some("0") | None => None,
+        Some(limit) => Some(parse_number(limit, "limit")?),
+    };
+    let filters = generate::Filters {
+        min_plays: optional_number(matches, "min-plays")?,
+        max_skips: optional_number(matches, "max-skips")?,
+        added_after: matches
+            .value_of("added-after")
+            .map(generate::parse_date)
+            .transpose()?,
+        played_after: matches
+            .value_of("played-after")
+            .map(generate::parse_date)
+            .transpose()?,
+        min_rating: min_rating(matches)?,
+    };
+    let name_prefix = matches.value_of("name-prefix").unwrap_or("");
+    let include_kinds = include_kinds(matches)?;
+    let match_mode: MatchMode = matches.value_of("match-by").unwrap().parse()?;
+    let workers = workers(matches)?;
+    let settings = playlist_settings(matches)?;
+
+    let library = load_all_tracks(path_to_library)?;
+    let (tracks, skipped_tracks) = partition_by_kind(library.tracks.values(), &include_kinds);
+    let candidates: Vec<Vec<usize>> = rules
+        .iter()
+        .map(|&rule| generate::select(&tracks, rule, &filters))
+        .collect();
+
+    let spotify_wrapper = spotify::SpotifyWrapper::new(spotify_client_id, spotify_client_secret)?;
+    // Tracks chosen by several rules are only matched once.
+    let mut matched_tracks: HashMap<usize, Option<FullTrack>> = HashMap::new();
+    for (rule, candidates) in rules.iter().zip(&candidates) {
+        // Candidates are matched a batch at a time until `limit` of them are found, so tracks
+        // missing from Spotify don't leave the playlist short.
+        let mut selection: Vec<usize> = Vec::new();
+        loop {
+            let found = selection
+                .iter()
+                .filter(|i| matched_tracks[i].is_some())
+                .count();
+            let wanted = limit.map_or(candidates.len(), |limit| limit.saturating_sub(found));
+            let batch =
+                &candidates[selection.len()..(selection.len() + wanted).min(candidates.len())];
+            if batch.is_empty() {
+                break;
+            }
+            let unmatched: Vec<usize> = batch
+                .iter()
+                .copied()
+                .filter(|i| !matched_tracks.contains_key(i))
+                .collect();
+            let source_tracks: Vec<SourceTrack> =
+                unmatched.iter().map(|&i| tracks[i].into()).collect();
+            let results = match_each_track(
+                &spotify_wrapper,
+                &source_tracks,
+                match_mode,
+                workers,
+                &mut Journal::default(),
+            )?;
+            matched_tracks.extend(unmatched.into_iter().zip(results));
+            selection.extend_from_slice(batch);
+        }
+
+        let matched: Vec<FullTrack> = selection
+            .iter()
+            .filter_map(|i| matched_tracks[i].clone())
+            .collect();
+        let rule_name = rule.playlist_name(limit);
+        let name = format!("{}{}", name_prefix, rule_name);
+        println!("{}", name);
+        if !print_only && !matched.is_empty() {
+            let selected_tracks: Vec<SourceTrack> =
+                selection.iter().map(|&i| tracks[i].into()).collect();
+            let origin = Origin {
+                name: &rule_name,
+                key: &rule_name,
+                files: &local_files(&selected_tracks),
+                total: selection.len(),
+            };
+            create_playlist_with_tracks(
+                &spotify_wrapper,
+                &settings,
+                &mut Journal::default(),
+                &name,
+                &origin,
+                &matched,
+            )?;
+        }
+        println!("matched tracks: {}", matched.len());
+        println!("total tracks: {}", selection.len());
+    }
+
+    output::print_skipped_kinds(&skipped_tracks);
+    output::print_track_warnings(&library.warnings);
+    Ok(())
+}
+
+/// Follow the artists and album artists of an iTunes library, or of some of its playlists, on
+/// Spotify.
+pub fn follow_artists(
+    spotify_client_id: &str,
+    spotify_client_secret: &str,
+    matches: &ArgMatches,
+) -> Result<(), Error> {
+    let path_to_library = matches.value_of("library").unwrap();
+    let print_only: bool = matches.is_present("print-only");
+    let pick_popular: bool = matches.is_present("pick-popular");
+    let folder_separator = matches.value_of("folder-separator").unwrap();
+    let min_tracks: usize = parse_number(matches.value_of("min-tracks").unwrap(), "min-tracks")?;
+    let selected_playlists: Option<Vec<&str>> =
+        matches.values_of("playlist").map(Iterator::collect);
+    let include_kinds = include_kinds(matches)?;
+
+    let is_selected = |library: &itunes::Playlist, playlist: &itunes::LibraryPlaylist| {
+        match selected_playlists {
+            Some(ref names) => {
+                let qualified_name = library.qualified_name(playlist, folder_separator);
+                !playlist.folder
+                    && (names.contains(&playlist.name.as_str())
+                        || names.contains(&qualified_name.as_str()))
+            }
+            // The master playlist references every track, a playlist export has none.
+            None => playlist.master || !library.playlists.iter().any(|p| p.master),
+        }
+    };
+
+    let library = itunes::stream::load_library(path_to_library, is_selected, log_load_progress())?;
+    let tracks: Vec<&itunes::Track> = match selected_playlists {
+        Some(_) => library
+            .playlists
+            .iter()
+            .filter(|playlist| is_selected(&library, playlist))
+            .flat_map(|playlist| library.playlist_tracks(playlist))
+            .collect(),
+        None => library.tracks.values().collect(),
+    };
+    let (tracks, skipped_tracks) = partition_by_kind(tracks, &include_kinds);
+    let library_artists: Vec<artists::LibraryArtist> = artists::library_artists(tracks)
+        .into_iter()
+        .filter(|artist| artist.tracks >= min_tracks)
+        .collect();
+
+    let spotify_wrapper = spotify::SpotifyWrapper::new(spotify_client_id, spotify_client_secret)?;
+    let mut artist_ids: Vec<String> = Vec::new();
+    let mut ambiguous = 0;
+    let mut not_found: Vec<&str> = Vec::new();
+    for artist in &library_artists {
+        let found = spotify_wrapper.search_artists(&artist.name, ARTIST_CANDIDATES)?;
+        let candidates: Vec<(&str, u32)> = found
+            .iter()
+            .map(|candidate| (candidate.name.as_str(), candidate.popularity))
+            .collect();
+        match artists::resolve_artist(&artist.name, &candidates, pick_popular) {
+            Resolution::Found(index) => {
+                debug!("{} is {}", artist.name, found[index].uri);
+                if !artist_ids.contains(&found[index].id) {
+                    artist_ids.push(found[index].id.clone());
+                }
+            }
+            Resolution::Ambiguous(indices) => {
+                ambiguous += 1;
+                println!(
+                    "{} ({} tracks) matches several artists, follow one by hand \
+                     or use --pick-popular:",
+                    artist.name, artist.tracks
+                );
+                let candidates: Vec<_> = indices.iter().map(|&index| &found[index]).collect();
+                output::tabulate_artists(&candidates);
+            }
+            Resolution::NotFound => not_found.push(&artist.name),
+        }
+    }
+
+    if !not_found.is_empty() {
+        println!("Artists not found on Spotify:");
+        for name in &not_found {
+            println!("    {}", name);
+        }
+    }
+    if !print_only && !artist_ids.is_empty() {
+        spotify_wrapper.follow_artists(&artist_ids)?;
+    }
+    println!("followed artists: {}", artist_ids.len());
+    println!("ambiguous artists: {}", ambiguous);
+    println!("artists not found: {}", not_found.len());
+    println!("total artists: {}", library_artists.len());
+
+    output::print_skipped_kinds(&skipped_tracks);
+    output::print_track_warnings(&library.warnings);
+    Ok(())
+}
+
+/// Load every track of an iTunes library or playlist export.
+fn load_all_tracks(path: &str) -> Result<itunes::Playlist, Error> {
+    // The master playlist references every track, a playlist export has none.
+    itunes::stream::load_library(
+        path,
+        |library, playlist| playlist.master || !library.playlists.iter().any(|p| p.master),
+        log_load_progress(),
+    )
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, Error> {
+    value.parse().map_err(|_| {
+        Error::new(ErrorKind::Config(format!(
+            "invalid {} {}, expected a number",
+            name, value
+        )))
+    })
+}
+
+fn optional_number<T: std::str::FromStr>(
+    matches: &ArgMatches,
+    name: &str,
+) -> Result<Option<T>, Error> {
+    matches
+        .value_of(name)
+        .map(|value| parse_number(value, name))
+        .transpose()
+}
+
+pub fn match_playlist(
+    spotify_client_id: &str,
+    spotify_client_secret: &str,
+    matches: &ArgMatches,
+) -> Result<(), Error> {
+    let to_library = matches.is_present("to-library");
+    convert_playlist(
+        spotify_client_id,
+        spotify_client_secret,
+        matches,
+        to_library,
+    )
 }
 
-pub fn match_playlist(spotify_client_id: &str, spotify_client_secret: &str, matches: &ArgMatches) {
-    let default_playlist_name = format!("ispot - {}", util::datetime_to_string());
+/// Save the matched tracks of a playlist to the user's Liked Songs.
+pub fn save_tracks(
+    spotify_client_id: &str,
+    spotify_client_secret: &str,
+    matches: &ArgMatches,
+) -> Result<(), Error> {
+    convert_playlist(spotify_client_id, spotify_client_secret, matches, true)
+}
+
+/// Match a playlist on Spotify, and create a Spotify playlist with the matched tracks or save them
+/// to the user's library.
+fn convert_playlist(
+    spotify_client_id: &str,
+    spotify_client_secret: &str,
+    matches: &ArgMatches,
+    to_library: bool,
+) -> Result<(), Error> {
     let path_to_playlist = matches.value_of("playlist").unwrap();
     let print_only: bool = matches.is_present("print-only");
-    let playlist_name = matches
-        .value_of("playlist-name")
-        .unwrap_or(&default_playlist_name);
+    let format = match matches.value_of("format") {
+        Some(format) => format.parse()?,
+        None => source::Format::detect(path_to_playlist),
+    };
+    let load_options = source::LoadOptions {
+        column_mappings: match matches.values_of("column") {
+            Some(columns) => columns.map(str::parse).collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        },
+        group_by_album: matches.is_present("group-by-album"),
+    };
+    let include_kinds = include_kinds(matches)?;
+    let match_mode: MatchMode = matches.value_of("match-by").unwrap().parse()?;
+    let workers = workers(matches)?;
+    let min_rating = min_rating(matches)?;
+    let settings = playlist_settings(matches)?;
+    let dedupe_by: DuplicateKey = matches
+        .value_of("dedupe-by")
+        .map(str::parse)
+        .transpose()?
+        .unwrap_or(DuplicateKey::Uri);
+    let keep: Keep = matches
+        .value_of("keep")
+        .map(str::parse)
+        .transpose()?
+        .unwrap_or(Keep::All);
+    let mut journal = journal(matches)?;
+    if min_rating.is_some() && format != source::Format::Itunes {
+        return Err(Error::new(ErrorKind::Config(
+            "--min-rating needs an iTunes playlist or library".to_string(),
+        )));
+    }
+
+    let spotify_wrapper = spotify::SpotifyWrapper::new(spotify_client_id, spotify_client_secret)?;
+
+    let itunes_playlist;
+    let mut matched_tracks;
+    let total_tracks;
+    let source_name;
+    let source_folder;
+    let source_files;
+    let skipped_tracks;
+    let warnings: Vec<String>;
+    match format {
+        source::Format::Itunes => {
+            itunes_playlist = itunes::load_playlist(path_to_playlist)?;
+            let playlist = &itunes_playlist;
+            let (tracks, skipped) = partition_by_kind(playlist.tracks.values(), &include_kinds);
+            let tracks: Vec<SourceTrack> = tracks
+                .into_iter()
+                .filter(|track| has_min_rating(track, min_rating))
+                .map(SourceTrack::from)
+                .collect();
+            matched_tracks =
+                match_tracks(&spotify_wrapper, &tracks, match_mode, workers, &mut journal)?;
+            // Tracks below the minimum rating were never part of the match.
+            total_tracks = tracks.len() + skipped.len();
+            source_files = local_files(&tracks);
+            let source_playlist = playlist.playlists.iter().find(|playlist| !playlist.master);
+            source_name = source_playlist.map(|source| source.name.clone());
+            source_folder = source_playlist
+                .map(|source| playlist.folder_path(source).join(FOLDER_SEPARATOR))
+                .unwrap_or_default();
+            skipped_tracks = skipped;
+            warnings = playlist.warnings.iter().map(ToString::to_string).collect();
+        }
+        _ => {
+            let playlist = source::load_playlist(path_to_playlist, format, &load_options)?;
+            matched_tracks = match_tracks(
+                &spotify_wrapper,
+                &playlist.tracks,
+                match_mode,
+                workers,
+                &mut journal,
+            )?;
+            total_tracks = playlist.tracks.len();
+            source_files = local_files(&playlist.tracks);
+            source_name = playlist.name.clone();
+            source_folder = String::new();
+            skipped_tracks = Vec::new();
+            warnings = playlist.warnings.iter().map(ToString::to_string).collect();
+        }
+    }
 
-    let spotify_wrapper =
-        spotify::SpotifyWrapper::new(&spotify_client_id, &spotify_client_secret).unwrap();
+    if matched_tracks.is_empty() {
+        return Err(Error::new(ErrorKind::NoResults).context("No track matches"));
+    }
 
-    let playlist = match itunes::load_playlist(&path_to_playlist) {
-        Ok(p) => p,
-        Err(e) => {
-            eprintln!("{}", e);
-            process::exit(1);
+    let duplicates =
+        dedupe::find_duplicates(matched_tracks.iter().map(|track| dedupe_by.of(track)));
+    if !duplicates.is_empty() {
+        println!("Duplicates");
+        output::tabulate_duplicates(&matched_tracks, &duplicates);
+        if keep == Keep::First {
+            dedupe::remove_duplicates(&mut matched_tracks, &duplicates);
         }
+    }
+
+    let saved = if print_only {
+        None
+    } else if to_library {
+        let save_albums = matches.is_present("albums");
+        Some(save_to_library(
+            &spotify_wrapper,
+            &matched_tracks,
+            save_albums,
+        )?)
+    } else {
+        let source_name = source_name.unwrap_or_else(|| match path_to_playlist {
+            itunes::STDIN_PATH => format!("ispot - {}", util::datetime_to_string()),
+            path => util::file_stem(path),
+        });
+        let playlist_name = match matches.value_of("playlist-name") {
+            Some(playlist_name) => playlist_name.to_string(),
+            None => {
+                let profile = profile_name(&spotify_wrapper, &settings)?;
+                let values = NameValues {
+                    source_name: &source_name,
+                    folder: &source_folder,
+                    date: &playlist::today(),
+                    count: matched_tracks.len(),
+                    profile: profile.as_deref(),
+                };
+                settings.name(&values, &source_name)?
+            }
+        };
+        let origin = Origin {
+            name: &source_name,
+            key: path_to_playlist,
+            files: &source_files,
+            total: total_tracks,
+        };
+        create_playlist_with_tracks(
+            &spotify_wrapper,
+            &settings,
+            &mut journal,
+            &playlist_name,
+            &origin,
+            &matched_tracks,
+        )?;
+        None
     };
-    let mut matched_tracks: Vec<FullTrack> = Vec::new();
 
-    for track in playlist.tracks.values() {
-        match spotify_wrapper.exact_track_match(
-            &track.name,
-            Some(&track.artist),
-            track.album.as_ref().map(|x| &**x),
-        ) {
-            Ok(spotify_track) => matched_tracks.push(spotify_track.clone()),
+    output::tabulate_tracks(&matched_tracks);
+    println!("matched tracks: {}", matched_tracks.len());
+    println!("total tracks: {}", total_tracks);
+    println!("duplicate tracks: {}", duplicates.len());
+    if let Some(saved) = saved {
+        println!("saved tracks: {}", saved.tracks);
+        println!("already saved tracks: {}", saved.already_saved_tracks);
+        if let Some(albums) = saved.albums {
+            println!("saved albums: {}", albums);
+        }
+    }
+    output::print_skipped_kinds(&skipped_tracks);
+    output::print_track_warnings(&warnings);
+    Ok(())
+}
+
+/// What `save_to_library` added to the user's library.
+struct SavedToLibrary {
+    tracks: usize,
+    already_saved_tracks: usize,
+    /// Number of albums saved, if albums were saved too.
+    albums: Option<usize>,
+}
+
+/// Save tracks, and optionally their albums, to the user's library.
+fn save_to_library(
+    spotify_wrapper: &spotify::SpotifyWrapper,
+    tracks: &[FullTrack],
+    save_albums: bool,
+) -> Result<SavedToLibrary, Error> {
+    let mut track_uris: Vec<String> = Vec::new();
+    for track in tracks {
+        if !track_uris.contains(&track.uri) {
+            track_uris.push(track.uri.clone());
+        }
+    }
+    let saved_tracks = spotify_wrapper
+        .save_tracks(&track_uris)
+        .map_err(|e| e.context("Failed to save tracks to the library"))?;
+
+    let mut saved_albums = None;
+    if save_albums {
+        let mut album_ids: Vec<String> = Vec::new();
+        for id in tracks.iter().filter_map(|track| track.album.id.as_ref()) {
+            if !album_ids.contains(id) {
+                album_ids.push(id.clone());
+            }
+        }
+        spotify_wrapper
+            .save_albums(&album_ids)
+            .map_err(|e| e.context("Failed to save albums to the library"))?;
+        saved_albums = Some(album_ids.len());
+    }
+
+    Ok(SavedToLibrary {
+        tracks: saved_tracks,
+        already_saved_tracks: track_uris.len() - saved_tracks,
+        albums: saved_albums,
+    })
+}
+
+/// Log how far through a large library file a streaming load is, in steps of 10%.
+fn log_load_progress() -> impl FnMut(itunes::stream::Progress) {
+    let mut last_reported = None;
+    move |progress| {
+        if progress.total_bytes == 0 {
+            return;
+        }
+        let percent = progress.bytes_read * 100 / progress.total_bytes / 10 * 10;
+        if last_reported == Some((progress.stage, percent)) {
+            return;
+        }
+        last_reported = Some((progress.stage, percent));
+        let stage = match progress.stage {
+            itunes::stream::Stage::Playlists => "reading playlists",
+            itunes::stream::Stage::Tracks => "reading tracks",
+        };
+        info!("{}: {}%", stage, percent);
+    }
+}
+
+/// How the user asked for the Spotify playlists to be named and set up.
+fn playlist_settings(matches: &ArgMatches) -> Result<PlaylistSettings, Error> {
+    PlaylistSettings::new(
+        matches.value_of("name-template").map(str::to_string),
+        matches
+            .value_of("visibility")
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        matches.value_of("description").map(str::to_string),
+        matches.value_of("cover").map(str::parse).transpose()?,
+    )
+}
+
+/// Number of tracks to search for at once.
+fn workers(matches: &ArgMatches) -> Result<usize, Error> {
+    match parse_number(matches.value_of("workers").unwrap(), "workers")? {
+        0 => Err(Error::new(ErrorKind::Config(
+            "workers must be at least 1".to_string(),
+        ))),
+        workers => Ok(workers),
+    }
+}
+
+/// The journal the user asked to record the conversion in, or one that records nothing.
+fn journal(matches: &ArgMatches) -> Result<Journal, Error> {
+    match matches.value_of("journal") {
+        Some(path) => Journal::open(path, matches.is_present("resume")),
+        None => Ok(Journal::default()),
+    }
+}
+
+/// The Spotify profile name, if the playlist names need it.
+fn profile_name(
+    spotify_wrapper: &spotify::SpotifyWrapper,
+    settings: &PlaylistSettings,
+) -> Result<Option<String>, Error> {
+    if settings.needs_profile() {
+        spotify_wrapper.profile_name().map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Media kinds the user asked to match in addition to music.
+fn include_kinds(matches: &ArgMatches) -> Result<Vec<itunes::MediaKind>, Error> {
+    match matches.values_of("include-kind") {
+        Some(kinds) => kinds.map(str::parse).collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The minimum rating in stars the user asked for.
+fn min_rating(matches: &ArgMatches) -> Result<Option<u8>, Error> {
+    let value = match matches.value_of("min-rating") {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    match value.parse::<u8>() {
+        Ok(stars) if (1..=5).contains(&stars) => Ok(Some(stars)),
+        _ => Err(Error::new(ErrorKind::Config(format!(
+            "invalid rating {}, expected 1 to 5 stars",
+            value
+        )))),
+    }
+}
+
+/// Whether a track is rated at least `min_rating` stars, always true without a minimum.
+fn has_min_rating(track: &itunes::Track, min_rating: Option<u8>) -> bool {
+    match min_rating {
+        Some(min_rating) => track.stars().is_some_and(|stars| stars >= min_rating),
+        None => true,
+    }
+}
+
+/// Split tracks into those to match and the non-music items to skip.
+fn partition_by_kind<'a, I>(
+    tracks: I,
+    include_kinds: &[itunes::MediaKind],
+) -> (Vec<&'a itunes::Track>, Vec<&'a itunes::Track>)
+where
+    I: IntoIterator<Item = &'a itunes::Track>,
+{
+    tracks.into_iter().partition(|track| {
+        let kind = track.media_kind();
+        kind == itunes::MediaKind::Music || include_kinds.contains(&kind)
+    })
+}
+
+/// Find each track on Spotify, leaving out tracks without a unique match.
+fn match_tracks(
+    spotify_wrapper: &spotify::SpotifyWrapper,
+    tracks: &[SourceTrack],
+    mode: MatchMode,
+    workers: usize,
+    journal: &mut Journal,
+) -> Result<Vec<FullTrack>, Error> {
+    let matched_tracks = match_each_track(spotify_wrapper, tracks, mode, workers, journal)?;
+    Ok(matched_tracks.into_iter().flatten().collect())
+}
+
+/// Find each track on Spotify, with `None` for tracks without a unique match.
+///
+/// Tracks are searched on `workers` threads at once, see `search_track`. With `MatchMode::Album`
+/// the albums of the tracks are matched first, see `match_albums`.
+///
+/// Tracks in the journal are not searched again, unless Spotify no longer has their match, and the
+/// others are journaled as they are matched.
+/// Progress is shown on stderr meanwhile, see `MatchProgress`.
+fn match_each_track(
+    spotify_wrapper: &spotify::SpotifyWrapper,
+    tracks: &[SourceTrack],
+    mode: MatchMode,
+    workers: usize,
+    journal: &mut Journal,
+) -> Result<Vec<Option<FullTrack>>, Error> {
+    let progress = MatchProgress::start(tracks.len());
+    let mut matched_tracks: Vec<Option<FullTrack>> = vec![None; tracks.len()];
+    let mut pending: Vec<usize> = Vec::new();
+    let mut journaled: Vec<(usize, String)> = Vec::new();
+    for (i, track) in tracks.iter().enumerate() {
+        match journal.track(track) {
+            Some(Some(uri)) => journaled.push((i, uri.to_string())),
+            Some(None) => progress.journaled_track(false),
+            None => pending.push(i),
+        }
+    }
+    if !journaled.is_empty() {
+        info!("{} tracks were matched before", journaled.len());
+        let (indices, uris): (Vec<usize>, Vec<String>) = journaled.into_iter().unzip();
+        let full_tracks = spotify_wrapper
+            .tracks(&uris)
+            .map_err(|e| e.context("Failed to look up the journaled tracks"))?;
+        for (i, full_track) in indices.into_iter().zip(full_tracks) {
+            match full_track {
+                Some(full_track) => {
+                    matched_tracks[i] = Some(full_track);
+                    progress.journaled_track(true);
+                }
+                // The track was taken off Spotify since, so it is searched again.
+                None => {
+                    info!(
+                        "{} is no longer on Spotify, searching it again",
+                        tracks[i].name
+                    );
+                    pending.push(i);
+                }
+            }
+        }
+        pending.sort_unstable();
+    }
+
+    if mode == MatchMode::Album {
+        let pending_tracks: Vec<SourceTrack> = pending.iter().map(|&i| tracks[i].clone()).collect();
+        let mut album_matches = vec![None; pending.len()];
+        match_albums(
+            spotify_wrapper,
+            &pending_tracks,
+            &mut album_matches,
+            journal,
+            &progress,
+        )?;
+        for (&i, album_match) in pending.iter().zip(album_matches) {
+            matched_tracks[i] = album_match;
+        }
+    }
+
+    let searches: Vec<usize> = pending
+        .into_iter()
+        .filter(|&i| matched_tracks[i].is_none())
+        .collect();
+    // Results arrive in whatever order the searches finish, but go in their own place.
+    search_concurrently(spotify_wrapper, tracks, &searches, workers, |i, result| {
+        let track = &tracks[i];
+        match result {
+            Ok(spotify_track) => {
+                journal.record_track(track, Some(&spotify_track.uri))?;
+                matched_tracks[i] = Some(spotify_track);
+                progress.track(true);
+            }
             Err(e) => match e.kind {
-                ErrorKind::MultipleResults(_) | ErrorKind::NoResults => (),
-                _ => panic!("unhandled exception {}", e),
+                ErrorKind::MultipleResults(_) | ErrorKind::NoResults => {
+                    journal.record_track(track, None)?;
+                    progress.track(false);
+                }
+                _ => return Err(e.context(format!("Failed to match track {}", track.name))),
             },
         }
-    }
+        Ok(())
+    })?;
+
+    Ok(matched_tracks)
+}
+
+/// Search for the tracks at `indices` on `workers` threads at once, handing each result to
+/// `found` on the calling thread.
+///
+/// The first error `found` returns stops the searches not started yet.
+fn search_concurrently<F>(
+    spotify_wrapper: &spotify::SpotifyWrapper,
+    tracks: &[SourceTrack],
+    indices: &[usize],
+    workers: usize,
+    mut found: F,
+) -> Result<(), Error>
+where
+    F: FnMut(usize, Result<FullTrack, Error>) -> Result<(), Error>,
+{
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..workers.min(indices.len()) {
+            let sender = sender.clone();
+            let next = &next;
+            scope.spawn(move || {
+                while let Some(&i) = indices.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    let result = search_track(spotify_wrapper, &tracks[i]);
+                    if sender.send((i, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        for (i, result) in receiver {
+            if let Err(e) = found(i, result) {
+                next.store(indices.len(), Ordering::SeqCst);
+                return Err(e);
+            }
+        }
+        Ok(())
+    })
+}
 
-    if matched_tracks.len() == 0 {
-        eprintln!("No track matches");
-        process::exit(1);
+/// Find a track on Spotify.
+///
+/// Tracks that already have a Spotify URI are looked up directly, and tracks with an ISRC are
+/// searched by it, before falling back to searching by name, artist and album.
+fn search_track(
+    spotify_wrapper: &spotify::SpotifyWrapper,
+    track: &SourceTrack,
+) -> Result<FullTrack, Error> {
+    match (&track.spotify_uri, &track.isrc) {
+        (Some(uri), _) => spotify_wrapper.track(uri),
+        (None, Some(isrc)) => spotify_wrapper.isrc_match(isrc).or_else(|e| match e.kind {
+            ErrorKind::NoResults => spotify_wrapper.exact_track_match(
+                &track.name,
+                track.artist.as_deref(),
+                track.album.as_deref(),
+            ),
+            _ => Err(e),
+        }),
+        (None, None) => spotify_wrapper.exact_track_match(
+            &track.name,
+            track.artist.as_deref(),
+            track.album.as_deref(),
+        ),
     }
+}
+
+/// Match the tracks of each album against the listings of the editions found on Spotify.
+///
+/// Tracks that aren't on the best edition are left unmatched, to be searched one by one. The
+/// others are journaled and counted in `progress` album by album.
+fn match_albums(
+    spotify_wrapper: &spotify::SpotifyWrapper,
+    tracks: &[SourceTrack],
+    matched_tracks: &mut [Option<FullTrack>],
+    journal: &mut Journal,
+    progress: &MatchProgress,
+) -> Result<(), Error> {
+    for album in matching::group_albums(tracks) {
+        let context = || format!("Failed to match album {} by {}", album.name, album.artist);
+        let editions = spotify_wrapper
+            .search_albums(&album.name, &album.artist, ALBUM_EDITIONS)
+            .map_err(|e| e.context(context()))?;
+        let mut listings = Vec::with_capacity(editions.len());
+        for edition in &editions {
+            let listing = match edition.id {
+                Some(ref id) => spotify_wrapper
+                    .album_tracks(id)
+                    .map_err(|e| e.context(context()))?,
+                None => Vec::new(),
+            };
+            listings.push(listing.iter().map(AlbumTrack::from).collect());
+        }
 
-    if !print_only {
-        let spotify_playlist = match spotify_wrapper.create_playlist(&playlist_name) {
-            Ok(p) => p,
-            Err(e) => {
-                eprintln!("{}", e);
-                process::exit(1);
+        let source: Vec<&SourceTrack> = album.tracks.iter().map(|&i| &tracks[i]).collect();
+        let edition_match = match matching::best_edition(&source, &listings) {
+            Some(edition_match) => edition_match,
+            None => {
+                info!(
+                    "album {} by {} not found, searching its tracks",
+                    album.name, album.artist
+                );
+                continue;
             }
         };
+        info!(
+            "album {} by {}: {} of {} tracks on {}",
+            album.name,
+            album.artist,
+            edition_match.matched(),
+            album.tracks.len(),
+            editions[edition_match.edition].name
+        );
 
-        for track in &matched_tracks {
-            //TODO: Don't unwrap here
-            spotify_wrapper
-                .add_track_to_playlist(&spotify_playlist.id, &track.uri)
-                .unwrap();
+        let (indices, uris): (Vec<usize>, Vec<String>) = album
+            .tracks
+            .iter()
+            .zip(edition_match.uris)
+            .filter_map(|(&i, uri)| uri.map(|uri| (i, uri)))
+            .unzip();
+        let full_tracks = spotify_wrapper
+            .tracks(&uris)
+            .map_err(|e| e.context(context()))?;
+        // Tracks Spotify can't look up are left to be searched one by one.
+        for (i, full_track) in indices.into_iter().zip(full_tracks) {
+            if let Some(full_track) = full_track {
+                journal.record_track(&tracks[i], Some(&full_track.uri))?;
+                matched_tracks[i] = Some(full_track);
+                progress.track(true);
+            }
         }
     }
+    Ok(())
+}
 
-    output::tabulate_tracks(&matched_tracks);
-    println!("matched tracks: {}", matched_tracks.len());
-    println!("total tracks: {}", playlist.tracks.len());
+/// The source playlist a Spotify playlist is converted from.
+struct Origin<'a> {
+    /// Name of the source playlist.
+    name: &'a str,
+    /// What identifies the source playlist in the journal, see `Journal::check_keys`.
+    key: &'a str,
+    /// Local audio files of its tracks, for those whose file is known.
+    files: &'a [PathBuf],
+    /// Number of its tracks, matched or not.
+    total: usize,
+}
+
+/// Create a Spotify playlist and add the tracks matched from `origin` to it.
+///
+/// A playlist the journal has a record of is completed instead of created again, from the number
+/// of tracks Spotify has in it, and skipped if it is complete already. A run that ends between
+/// creating a playlist and journaling it leaves an empty playlist behind, which the next run
+/// creates again.
+fn create_playlist_with_tracks(
+    spotify_wrapper: &spotify::SpotifyWrapper,
+    settings: &PlaylistSettings,
+    journal: &mut Journal,
+    name: &str,
+    origin: &Origin,
+    tracks: &[FullTrack],
+) -> Result<(), Error> {
+    let progress = journal.playlist(origin.key);
+    if progress.done {
+        info!("{} was converted before, skipping it", origin.name);
+        return Ok(());
+    }
+    let (playlist_id, added) = match progress.id {
+        Some(id) => {
+            // The journal can't tell whether the last track was added before the run ended.
+            let added = spotify_wrapper
+                .playlist_tracks(&id)
+                .map_err(|e| e.context(format!("Failed to resume {}", name)))?
+                .len();
+            info!("resuming {} after {} tracks", name, added);
+            (id, added)
+        }
+        None => {
+            let spotify_playlist =
+                spotify_wrapper.create_playlist(name, settings.visibility, None)?;
+            journal.record_created(origin.key, &spotify_playlist.id)?;
+            (spotify_playlist.id, 0)
+        }
+    };
+
+    for track in tracks.iter().skip(added) {
+        spotify_wrapper
+            .add_track_to_playlist(&playlist_id, &track.uri)
+            .map_err(|e| e.context(format!("Failed to add track {}", track.name)))?;
+    }
+    // The description tells how the last sync went, so it is written once the tracks are in.
+    if let Some(description) = settings.description(origin.name, tracks.len(), origin.total)? {
+        spotify_wrapper
+            .set_playlist_description(&playlist_id, &description)
+            .map_err(|e| e.context(format!("Failed to set the description of {}", name)))?;
+    }
+
+    // The playlist is there and complete even without its cover, so this is not an error.
+    if let Some(ref cover) = settings.cover {
+        match upload_cover(spotify_wrapper, &playlist_id, cover, origin.files, tracks) {
+            Ok(true) => (),
+            Ok(false) => warn!("no cover image found for {}", name),
+            Err(e) => warn!("failed to upload the cover of {}: {}", name, e),
+        }
+    }
+
+    journal.record_done(origin.key)
+}
+
+/// Set the cover of a playlist. Returns whether a cover image was found.
+fn upload_cover(
+    spotify_wrapper: &spotify::SpotifyWrapper,
+    playlist_id: &str,
+    cover: &CoverSource,
+    files: &[PathBuf],
+    tracks: &[FullTrack],
+) -> Result<bool, Error> {
+    let image = match cover {
+        CoverSource::File(path) => Some(cover::load_image(path)?),
+        CoverSource::Artwork => files.iter().find_map(|path| {
+            path.is_file()
+                .then(|| cover::embedded_artwork(path))
+                .flatten()
+        }),
+        CoverSource::Mosaic => {
+            let images = cover::album_cover_urls(tracks, MOSAIC_ALBUMS)
+                .into_iter()
+                .map(cover::download_image)
+                .collect::<Result<Vec<_>, _>>()?;
+            cover::mosaic(&images)
+        }
+    };
+    match image {
+        Some(image) => {
+            let jpeg = cover::encode_cover(&image)?;
+            spotify_wrapper.upload_playlist_cover(playlist_id, &jpeg)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// The local audio files of tracks, for those whose file is known.
+fn local_files(tracks: &[SourceTrack]) -> Vec<PathBuf> {
+    tracks
+        .iter()
+        .filter_map(|track| track.path.clone())
+        .collect()
 }
 
-pub fn match_track(spotify_client_id: &str, spotify_client_secret: &str, matches: &ArgMatches) {
+pub fn match_track(
+    spotify_client_id: &str,
+    spotify_client_secret: &str,
+    matches: &ArgMatches,
+) -> Result<(), Error> {
     let name = matches.value_of("name").unwrap();
     let artist = matches.value_of("artist").unwrap();
     let album = matches.value_of("album").unwrap();
 
-    let spotify_wrapper =
-        spotify::SpotifyWrapper::new(&spotify_client_id, &spotify_client_secret).unwrap();
-    let track = match spotify_wrapper.exact_track_match(&name, Some(&artist), Some(&album)) {
-        Ok(t) => t,
-        Err(e) => {
-            eprintln!("{}", e);
-            process::exit(1);
-        }
-    };
+    let spotify_wrapper = spotify::SpotifyWrapper::new(spotify_client_id, spotify_client_secret)?;
+    let track = spotify_wrapper.exact_track_match(name, Some(artist), Some(album))?;
     output::tabulate_track(&track);
+    Ok(())
+}
+
+/// List the playlists and playlist folders of an iTunes library.
+pub fn list_library_playlists(matches: &ArgMatches) -> Result<(), Error> {
+    let path_to_library = matches.value_of("library").unwrap();
+    let include_builtin: bool = matches.is_present("include-builtin");
+
+    let library = itunes::stream::load_playlists(path_to_library, log_load_progress())?;
+    output::tabulate_library_playlists(&library, include_builtin);
+    Ok(())
+}
+
+/// Report which tracks of a library or playlist are local files, and whether they exist.
+pub fn check_files(matches: &ArgMatches) -> Result<(), Error> {
+    let path_to_playlist = matches.value_of("playlist").unwrap();
+    let missing_only: bool = matches.is_present("missing-only");
+
+    let playlist = itunes::load_playlist(path_to_playlist)?;
+    let resolver = path_resolver(matches, &playlist)?;
+
+    let mut files = Vec::new();
+    let mut not_local = 0;
+    for track in playlist.tracks.values() {
+        match resolver.local_file(track) {
+            Some(file) => files.push((track, file)),
+            None => not_local += 1,
+        }
+    }
+    let found = files.iter().filter(|(_, file)| file.exists).count();
+    let missing = files.len() - found;
+
+    if missing_only {
+        files.retain(|(_, file)| !file.exists);
+    }
+    output::tabulate_local_files(&files);
+    println!("found files: {}", found);
+    println!("missing files: {}", missing);
+    println!("not local files: {}", not_local);
+    output::print_track_warnings(&playlist.warnings);
+    Ok(())
 }
 
-pub fn show_playlist(matches: &ArgMatches) {
+/// Write an iTunes playlist, or a playlist of a library, to another playlist format.
+pub fn export(matches: &ArgMatches) -> Result<(), Error> {
     let path_to_playlist = matches.value_of("playlist").unwrap();
+    let format: export::Format = matches.value_of("format").unwrap().parse()?;
+    let output_path = matches.value_of("output");
+    let folder_separator = matches.value_of("folder-separator").unwrap();
 
-    let playlist = match itunes::load_playlist(&path_to_playlist) {
-        Ok(p) => p,
-        Err(e) => {
-            eprintln!("{}", e);
-            process::exit(1);
+    let library = itunes::load_playlist(path_to_playlist)?;
+    let resolver = path_resolver(matches, &library)?;
+
+    let (title, tracks) = match matches.value_of("name") {
+        Some(name) => {
+            let playlist = library
+                .find_playlist(name, folder_separator)
+                .ok_or_else(|| {
+                    Error::new(ErrorKind::Config(format!(
+                        "{} has no playlist named {}",
+                        path_to_playlist, name
+                    )))
+                })?;
+            (playlist.name.clone(), library.playlist_tracks(playlist))
+        }
+        None => {
+            let title = match library.playlists.as_slice() {
+                [playlist] => playlist.name.clone(),
+                _ => util::file_stem(path_to_playlist),
+            };
+            (title, library.tracks_in_order())
         }
     };
 
+    let entries: Vec<export::Entry> = tracks
+        .into_iter()
+        .map(|track| export::Entry {
+            track,
+            path: track
+                .location
+                .as_ref()
+                .and_then(|location| resolver.resolve(location)),
+        })
+        .collect();
+
+    let result = match output_path {
+        Some(path) => File::create(path).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            export::write(format, &title, &entries, &mut out)?;
+            out.flush()
+        }),
+        None => export::write(format, &title, &entries, &mut io::stdout().lock()),
+    };
+    result.map_err(|e| {
+        Error::with_source(
+            ErrorKind::Io(output_path.unwrap_or("stdout").to_string()),
+            e,
+        )
+    })?;
+
+    // The export may be going to stdout, so report skipped tracks on stderr.
+    for warning in &library.warnings {
+        warn!("skipped {}", warning);
+    }
+    Ok(())
+}
+
+/// Build a path resolver from the `--remap` and `--music-folder` arguments.
+fn path_resolver(
+    matches: &ArgMatches,
+    library: &itunes::Playlist,
+) -> Result<itunes::location::PathResolver, Error> {
+    let mut remaps: Vec<itunes::location::Remap> = match matches.values_of("remap") {
+        Some(remaps) => remaps.map(str::parse).collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+    if let Some(music_folder) = matches.value_of("music-folder") {
+        let old_music_folder = library.music_folder.as_ref().ok_or_else(|| {
+            Error::new(ErrorKind::Config(
+                "the playlist has no Music Folder to replace".to_string(),
+            ))
+        })?;
+        remaps.push(itunes::location::Remap::new(old_music_folder, music_folder));
+    }
+    Ok(itunes::location::PathResolver::new(remaps))
+}
+
+pub fn show_playlist(matches: &ArgMatches) -> Result<(), Error> {
+    let path_to_playlist = matches.value_of("playlist").unwrap();
+
+    let playlist = itunes::load_playlist(path_to_playlist)?;
+
     let tracks: Vec<_> = playlist.tracks.values().cloned().collect();
     output::tabulate_tracks(&tracks);
+    output::print_track_warnings(&playlist.warnings);
+    Ok(())
 }
@@ -0,0 +1,238 @@
+//! Cover images of Spotify playlists: an image file, the artwork of a local audio file, or a
+//! mosaic of album covers.
+use crate::error::{Error, ErrorKind};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::{self, FilterType};
+use image::DynamicImage;
+use lofty::picture::PictureType;
+use lofty::prelude::*;
+use rspotify::spotify::model::track::FullTrack;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Largest cover Spotify accepts, in bytes of the base64 encoded JPEG.
+const MAX_COVER_SIZE: usize = 256 * 1024;
+/// Width and height of the uploaded cover. Spotify shows covers at 640 pixels at most.
+const COVER_SIZE: u32 = 640;
+/// JPEG qualities tried in turn until the cover fits in `MAX_COVER_SIZE`.
+const JPEG_QUALITIES: &[u8] = &[90, 80, 70, 60, 50, 40];
+
+/// Where the cover of a playlist comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CoverSource {
+    /// A JPEG or PNG file.
+    File(String),
+    /// The artwork embedded in the first local audio file of the playlist that has any.
+    Artwork,
+    /// The covers of the first four albums of the matched tracks, side by side.
+    Mosaic,
+}
+
+impl FromStr for CoverSource {
+    type Err = Error;
+
+    /// Parse `artwork`, `mosaic` or the path of an image. A file named like one of the keywords
+    /// can be given as e.g. `./mosaic`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "artwork" => Ok(CoverSource::Artwork),
+            "mosaic" => Ok(CoverSource::Mosaic),
+            "" => Err(Error::new(ErrorKind::Config(
+                "cover needs artwork, mosaic or an image file".to_string(),
+            ))),
+            path => Ok(CoverSource::File(path.to_string())),
+        }
+    }
+}
+
+/// Load a JPEG or PNG file.
+pub fn load_image(path: &str) -> Result<DynamicImage, Error> {
+    if !Path::new(path).is_file() {
+        return Err(Error::new(ErrorKind::FileNotFound(format!(
+            "Can't find cover image {}",
+            path
+        ))));
+    }
+    image::open(path).map_err(|e| {
+        Error::new(ErrorKind::Parse {
+            path: path.to_string(),
+            detail: e.to_string(),
+        })
+    })
+}
+
+/// The artwork embedded in an audio file, preferring the front cover.
+pub fn embedded_artwork(path: &Path) -> Option<DynamicImage> {
+    let file = match lofty::read_from_path(path) {
+        Ok(file) => file,
+        Err(e) => {
+            debug!("no artwork read from {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let pictures: Vec<_> = file.tags().iter().flat_map(|tag| tag.pictures()).collect();
+    let picture = pictures
+        .iter()
+        .find(|picture| picture.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures.first())?;
+    match image::load_from_memory(picture.data()) {
+        Ok(image) => Some(image),
+        Err(e) => {
+            debug!("unreadable artwork in {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Download an image, e.g. an album cover from Spotify's image CDN.
+pub fn download_image(url: &str) -> Result<DynamicImage, Error> {
+    let network_error = |e: reqwest::Error| {
+        Error::with_source(ErrorKind::Network(format!("Failed to download {}", url)), e)
+    };
+    let mut response = reqwest::get(url)
+        .and_then(|response| response.error_for_status())
+        .map_err(network_error)?;
+    let mut bytes = Vec::new();
+    response.read_to_end(&mut bytes).map_err(|e| {
+        Error::with_source(ErrorKind::Network(format!("Failed to download {}", url)), e)
+    })?;
+    image::load_from_memory(&bytes).map_err(|e| {
+        Error::new(ErrorKind::Parse {
+            path: url.to_string(),
+            detail: e.to_string(),
+        })
+    })
+}
+
+/// URLs of the covers of the first `count` albums of `tracks`, in track order.
+pub fn album_cover_urls(tracks: &[FullTrack], count: usize) -> Vec<&str> {
+    let mut urls: Vec<&str> = Vec::with_capacity(count);
+    // Spotify lists the images of an album widest first.
+    for image in tracks.iter().filter_map(|track| track.album.images.first()) {
+        if urls.len() == count {
+            break;
+        }
+        if !urls.contains(&image.url.as_str()) {
+            urls.push(&image.url);
+        }
+    }
+    urls
+}
+
+/// Tile four images into a square of two by two, or use a single image as is.
+///
+/// Like Spotify's own covers, a mosaic needs four images; with fewer only the first is used.
+pub fn mosaic(images: &[DynamicImage]) -> Option<DynamicImage> {
+    if images.len() < 4 {
+        return images.first().cloned();
+    }
+    let tile = COVER_SIZE / 2;
+    let mut cover = DynamicImage::new_rgb8(COVER_SIZE, COVER_SIZE);
+    for (i, image) in images.iter().take(4).enumerate() {
+        let tile_image = image.resize_to_fill(tile, tile, FilterType::Lanczos3);
+        let x = (i as u32 % 2) * tile;
+        let y = (i as u32 / 2) * tile;
+        imageops::overlay(&mut cover, &tile_image, i64::from(x), i64::from(y));
+    }
+    Some(cover)
+}
+
+/// Crop an image to a square, scale it to the cover size and encode it as a JPEG small enough to
+/// upload.
+pub fn encode_cover(image: &DynamicImage) -> Result<Vec<u8>, Error> {
+    let mut size = COVER_SIZE.min(image.width()).min(image.height()).max(1);
+    loop {
+        let square = image
+            .resize_to_fill(size, size, FilterType::Lanczos3)
+            .to_rgb8();
+        for &quality in JPEG_QUALITIES {
+            let mut jpeg = Vec::new();
+            JpegEncoder::new_with_quality(&mut jpeg, quality)
+                .encode_image(&square)
+                .map_err(|e| cover_error(format!("failed to encode as JPEG: {}", e)))?;
+            if base64_len(&jpeg) <= MAX_COVER_SIZE {
+                return Ok(jpeg);
+            }
+        }
+        if size <= 64 {
+            return Err(cover_error("too large to upload".to_string()));
+        }
+        size /= 2;
+    }
+}
+
+/// An image that can't be made into a cover, which has no path of its own once loaded.
+fn cover_error(detail: String) -> Error {
+    Error::new(ErrorKind::Parse {
+        path: "cover image".to_string(),
+        detail,
+    })
+}
+
+/// Length of `bytes` encoded as padded base64, as they are uploaded.
+fn base64_len(bytes: &[u8]) -> usize {
+    base64::encoded_len(bytes.len(), true).unwrap_or(usize::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64_len, encode_cover, mosaic, CoverSource, COVER_SIZE, MAX_COVER_SIZE};
+    use image::{DynamicImage, Rgb, RgbImage};
+
+    fn noise(width: u32, height: u32) -> DynamicImage {
+        let mut seed: u32 = 1;
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |_, _| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let [r, g, b, _] = seed.to_le_bytes();
+            Rgb([r, g, b])
+        }))
+    }
+
+    #[test]
+    fn test_cover_source() {
+        assert_eq!(
+            "artwork".parse::<CoverSource>().unwrap(),
+            CoverSource::Artwork
+        );
+        assert_eq!(
+            "mosaic".parse::<CoverSource>().unwrap(),
+            CoverSource::Mosaic
+        );
+        assert_eq!(
+            "./mosaic".parse::<CoverSource>().unwrap(),
+            CoverSource::File("./mosaic".to_string())
+        );
+        assert!("".parse::<CoverSource>().is_err());
+    }
+
+    #[test]
+    fn test_mosaic() {
+        let red = DynamicImage::ImageRgb8(RgbImage::from_pixel(300, 200, Rgb([255, 0, 0])));
+        let blue = DynamicImage::ImageRgb8(RgbImage::from_pixel(100, 100, Rgb([0, 0, 255])));
+        assert!(mosaic(&[]).is_none());
+        assert_eq!(mosaic(std::slice::from_ref(&red)).unwrap().width(), 300);
+
+        let cover = mosaic(&[red.clone(), blue.clone(), blue, red])
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(cover.dimensions(), (COVER_SIZE, COVER_SIZE));
+        assert_eq!(cover.get_pixel(10, 10), &Rgb([255, 0, 0]));
+        assert_eq!(cover.get_pixel(COVER_SIZE - 10, 10), &Rgb([0, 0, 255]));
+        assert_eq!(cover.get_pixel(10, COVER_SIZE - 10), &Rgb([0, 0, 255]));
+        assert_eq!(
+            cover.get_pixel(COVER_SIZE - 10, COVER_SIZE - 10),
+            &Rgb([255, 0, 0])
+        );
+    }
+
+    #[test]
+    fn test_encode_cover() {
+        // Noise compresses badly, so this needs a lower quality or a smaller cover to fit.
+        let jpeg = encode_cover(&noise(1200, 900)).unwrap();
+        assert!(base64_len(&jpeg) <= MAX_COVER_SIZE);
+        let cover = image::load_from_memory(&jpeg).unwrap();
+        assert_eq!(cover.width(), cover.height());
+        assert!(cover.width() <= COVER_SIZE);
+    }
+}
@@ -0,0 +1,278 @@
+//! Duplicate tracks of a playlist: the same Spotify track more than once, or the same song from
+//! several albums.
+use crate::artists::normalize_artist;
+use crate::error::{Error, ErrorKind};
+use crate::matching::normalize_title;
+use rspotify::spotify::model::track::FullTrack;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// What makes two tracks of a playlist duplicates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DuplicateKey {
+    /// The same Spotify track.
+    Uri,
+    /// The same title and artist, ignoring edition suffixes like `- Remastered`, so the song
+    /// from an album and from a compilation are duplicates too.
+    Title,
+}
+
+impl DuplicateKey {
+    /// Names accepted on the command line.
+    pub const NAMES: &'static [&'static str] = &["uri", "title"];
+
+    /// The key of a track, or `None` for tracks that are never duplicates, like local files.
+    pub(crate) fn of(self, track: &FullTrack) -> Option<String> {
+        track.id.as_ref()?;
+        match self {
+            DuplicateKey::Uri => Some(track.uri.clone()),
+            DuplicateKey::Title => {
+                let artist = track
+                    .artists
+                    .first()
+                    .map_or("", |artist| artist.name.as_str());
+                Some(title_key(&track.name, artist))
+            }
+        }
+    }
+}
+
+impl FromStr for DuplicateKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uri" => Ok(DuplicateKey::Uri),
+            "title" => Ok(DuplicateKey::Title),
+            _ => Err(Error::new(ErrorKind::Config(format!(
+                "unknown duplicate key {}",
+                s
+            )))),
+        }
+    }
+}
+
+/// Which of several duplicates stay in a playlist.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Keep {
+    /// Only the first of the duplicates.
+    First,
+    /// All of them, only reporting the duplicates.
+    All,
+}
+
+impl Keep {
+    /// Names accepted on the command line.
+    pub const NAMES: &'static [&'static str] = &["first", "all"];
+}
+
+impl FromStr for Keep {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(Keep::First),
+            "all" => Ok(Keep::All),
+            _ => Err(Error::new(ErrorKind::Config(format!(
+                "unknown duplicate handling {}",
+                s
+            )))),
+        }
+    }
+}
+
+/// A track that duplicates an earlier track of the same playlist.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Duplicate {
+    /// Index of the duplicate.
+    pub index: usize,
+    /// Index of the first track it duplicates.
+    pub first: usize,
+}
+
+/// Find the tracks whose key is the key of an earlier track, in playlist order.
+pub(crate) fn find_duplicates<I, K>(keys: I) -> Vec<Duplicate>
+where
+    I: IntoIterator<Item = Option<K>>,
+    K: std::hash::Hash + Eq,
+{
+    let mut firsts: HashMap<K, usize> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for (index, key) in keys.into_iter().enumerate() {
+        if let Some(key) = key {
+            let first = *firsts.entry(key).or_insert(index);
+            if first != index {
+                duplicates.push(Duplicate { index, first });
+            }
+        }
+    }
+    duplicates
+}
+
+/// Remove the duplicates from `tracks`, keeping the first of each. `duplicates` are in playlist
+/// order, as [`find_duplicates`] returns them.
+pub(crate) fn remove_duplicates<T>(tracks: &mut Vec<T>, duplicates: &[Duplicate]) {
+    let mut index = 0;
+    let mut duplicates = duplicates
+        .iter()
+        .map(|duplicate| duplicate.index)
+        .peekable();
+    tracks.retain(|_| {
+        let duplicate = duplicates.next_if_eq(&index).is_some();
+        index += 1;
+        !duplicate
+    });
+}
+
+/// Occurrences of a track in a Spotify playlist, as the API takes them to remove only those.
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Occurrences<'a> {
+    pub uri: &'a str,
+    /// Positions of the track, counting from 0.
+    pub positions: Vec<usize>,
+}
+
+/// Plan the removal of `duplicates` from a playlist of tracks `uris`, in requests of at most
+/// `size` positions each.
+///
+/// The requests go from the end of the playlist to its start, so the positions of each request
+/// still hold once the ones before it are removed. `uris` have to be one version of the playlist:
+/// positions from an outdated listing would remove whatever tracks are there now.
+pub(crate) fn plan_removals<'a>(
+    uris: &[&'a str],
+    duplicates: &[Duplicate],
+    size: usize,
+) -> Vec<Vec<Occurrences<'a>>> {
+    let positions: Vec<usize> = duplicates
+        .iter()
+        .rev()
+        .map(|duplicate| duplicate.index)
+        .collect();
+    positions
+        .chunks(size)
+        .map(|chunk| {
+            let mut request: Vec<Occurrences> = Vec::new();
+            for &position in chunk {
+                let uri = uris[position];
+                match request
+                    .iter_mut()
+                    .find(|occurrences| occurrences.uri == uri)
+                {
+                    Some(occurrences) => occurrences.positions.push(position),
+                    None => request.push(Occurrences {
+                        uri,
+                        positions: vec![position],
+                    }),
+                }
+            }
+            request
+        })
+        .collect()
+}
+
+/// What the same song has in common across albums.
+fn title_key(name: &str, artist: &str) -> String {
+    format!(
+        "{}\u{1f}{}",
+        normalize_title(name),
+        normalize_artist(artist)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        find_duplicates, plan_removals, remove_duplicates, title_key, Duplicate, Occurrences,
+    };
+
+    #[test]
+    fn test_find_duplicates() {
+        let keys = vec![
+            Some("a"),
+            Some("b"),
+            None,
+            Some("a"),
+            None,
+            Some("b"),
+            Some("a"),
+        ];
+        let duplicates = find_duplicates(keys);
+        assert_eq!(
+            duplicates,
+            vec![
+                Duplicate { index: 3, first: 0 },
+                Duplicate { index: 5, first: 1 },
+                Duplicate { index: 6, first: 0 },
+            ]
+        );
+
+        let mut tracks = vec!["a", "b", "local", "a", "local", "b", "a"];
+        remove_duplicates(&mut tracks, &duplicates);
+        assert_eq!(tracks, vec!["a", "b", "local", "local"]);
+    }
+
+    #[test]
+    fn test_plan_removals() {
+        let uris = ["b", "a", "b", "c", "a", "b"];
+        let duplicates = find_duplicates(uris.iter().map(Some));
+        assert_eq!(
+            plan_removals(&uris, &duplicates, 100),
+            vec![vec![
+                Occurrences {
+                    uri: "b",
+                    positions: vec![5, 2],
+                },
+                Occurrences {
+                    uri: "a",
+                    positions: vec![4],
+                },
+            ]]
+        );
+        assert_eq!(
+            plan_removals(&uris, &duplicates, 2),
+            vec![
+                vec![
+                    Occurrences {
+                        uri: "b",
+                        positions: vec![5],
+                    },
+                    Occurrences {
+                        uri: "a",
+                        positions: vec![4],
+                    },
+                ],
+                vec![Occurrences {
+                    uri: "b",
+                    positions: vec![2],
+                }],
+            ]
+        );
+
+        // Another edition of the first track: only its own occurrences are removed.
+        let uris = ["x1", "y", "x2", "x2"];
+        let duplicates = vec![
+            Duplicate { index: 2, first: 0 },
+            Duplicate { index: 3, first: 0 },
+        ];
+        assert_eq!(
+            plan_removals(&uris, &duplicates, 100),
+            vec![vec![Occurrences {
+                uri: "x2",
+                positions: vec![3, 2],
+            }]]
+        );
+    }
+
+    #[test]
+    fn test_title_key() {
+        assert_eq!(
+            title_key("Bohemian Rhapsody - Remastered 2011", "Queen"),
+            title_key("Bohemian Rhapsody", "queen")
+        );
+        assert_ne!(
+            title_key("Bohemian Rhapsody", "Queen"),
+            title_key("Bohemian Rhapsody", "Panic! At The Disco")
+        );
+    }
+}
@@ -1,31 +1,120 @@
+//! Error types shared by all ispot commands.
+//!
+//! Every error maps to a process exit code so scripts can tell failure classes apart:
+//!
+//! | Code | Meaning                                                  |
+//! |------|----------------------------------------------------------|
+//! | 0    | Success                                                  |
+//! | 1    | No (unique) match was found on Spotify                   |
+//! | 65   | The input playlist could not be parsed                   |
+//! | 66   | The input file does not exist or is not a file           |
+//! | 69   | The Spotify API could not be reached or returned an error |
+//! | 74   | Reading or writing a local file failed                   |
+//! | 75   | Rate limiting persisted, or a playlist changed meanwhile |
+//! | 77   | Authentication with Spotify failed or was rejected       |
+//! | 78   | The configuration or command line arguments are invalid  |
+//!
+//! The codes follow the BSD `sysexits.h` conventions.
 use std::error;
 use std::fmt;
+use std::sync::Arc;
+
+/// Exit code used when no (unique) match was found on Spotify.
+pub const EXIT_NO_MATCH: i32 = 1;
+/// Exit code used when the input data is malformed (`EX_DATAERR`).
+pub const EXIT_DATA_ERROR: i32 = 65;
+/// Exit code used when an input file is missing (`EX_NOINPUT`).
+pub const EXIT_NO_INPUT: i32 = 66;
+/// Exit code used when the Spotify API is unavailable or fails (`EX_UNAVAILABLE`).
+pub const EXIT_UNAVAILABLE: i32 = 69;
+/// Exit code used when a local IO operation fails (`EX_IOERR`).
+pub const EXIT_IO_ERROR: i32 = 74;
+/// Exit code used when a temporary failure persists, e.g. rate limiting (`EX_TEMPFAIL`).
+pub const EXIT_TEMPORARY_FAILURE: i32 = 75;
+/// Exit code used when authentication or authorization fails (`EX_NOPERM`).
+pub const EXIT_NO_PERMISSION: i32 = 77;
+/// Exit code used when the configuration is invalid (`EX_CONFIG`).
+pub const EXIT_CONFIG_ERROR: i32 = 78;
 
 #[derive(Clone, Debug)]
 pub struct Error {
     pub kind: ErrorKind,
+    context: Option<String>,
+    source: Option<Arc<dyn error::Error + Send + Sync>>,
 }
 
 impl Error {
     pub fn new(kind: ErrorKind) -> Error {
-        Error { kind }
+        Error {
+            kind,
+            context: None,
+            source: None,
+        }
+    }
+
+    /// Create an error that keeps the underlying error as its source.
+    pub fn with_source<E>(kind: ErrorKind, source: E) -> Error
+    where
+        E: error::Error + Send + Sync + 'static,
+    {
+        Error {
+            kind,
+            context: None,
+            source: Some(Arc::new(source)),
+        }
+    }
+
+    /// Describe what was being done when the error occurred, e.g. the file being read.
+    pub fn context<S: Into<String>>(mut self, context: S) -> Error {
+        self.context = Some(context.into());
+        self
+    }
+
+    /// The process exit code for this error. See the module documentation for the full list.
+    pub fn exit_code(&self) -> i32 {
+        match self.kind {
+            ErrorKind::ApiStatus { .. } | ErrorKind::Network(_) => EXIT_UNAVAILABLE,
+            ErrorKind::AuthenticationFailure(_) | ErrorKind::Unauthorized => EXIT_NO_PERMISSION,
+            ErrorKind::Config(_) => EXIT_CONFIG_ERROR,
+            ErrorKind::FileNotFound(_) | ErrorKind::NotAFile(_) => EXIT_NO_INPUT,
+            ErrorKind::Io(_) => EXIT_IO_ERROR,
+            ErrorKind::MultipleResults(_) | ErrorKind::NoResults => EXIT_NO_MATCH,
+            ErrorKind::Parse { .. } | ErrorKind::PlistError { .. } => EXIT_DATA_ERROR,
+            ErrorKind::PlaylistChanged(_) | ErrorKind::RateLimitExhausted(_) => {
+                EXIT_TEMPORARY_FAILURE
+            }
+        }
     }
 }
 
 #[derive(Clone, Debug)]
 pub enum ErrorKind {
+    /// This error occurs if the Spotify API responds with an unexpected HTTP status.
+    ApiStatus { status: u16, body: Option<String> },
     /// This error occurs if the user fails to authenticate with Spotify.
     AuthenticationFailure(String),
+    /// This error occurs if the configuration or command line arguments are invalid.
+    Config(String),
     /// This error occurs if the file is not found.
     FileNotFound(String),
+    /// This error occurs if reading or writing a local file fails.
+    Io(String),
     /// This error occurs if multiple results are returned and we expected a different amount.
     MultipleResults(u32),
+    /// This error occurs if a request to the Spotify API fails or its response can't be read.
+    Network(String),
     /// This error occurs if a directory is found instead of a file.
     NotAFile(String),
     /// This error occurs if no results are returned from the Spotify API.
     NoResults,
+    /// This error occurs if a playlist file other than a plist can't be parsed.
+    Parse { path: String, detail: String },
     /// This error occurs when trying to load a plist (iTunes playlist).
-    PlistError(String),
+    PlistError { path: String, detail: String },
+    /// This error occurs if a Spotify playlist changes while it is read to be edited.
+    PlaylistChanged(String),
+    /// This error occurs if the Spotify API keeps rate limiting requests after all retries.
+    RateLimitExhausted(u32),
     /// This error occurs if an API calls is not authorized.
     Unauthorized,
 }
@@ -33,27 +122,111 @@ pub enum ErrorKind {
 impl error::Error for Error {
     fn description(&self) -> &str {
         match self.kind {
+            ErrorKind::ApiStatus { .. } => "spotify api call failed",
             ErrorKind::AuthenticationFailure(_) => "failure authenticating with Spotify",
+            ErrorKind::Config(_) => "invalid configuration",
             ErrorKind::FileNotFound(_) => "file not found",
+            ErrorKind::Io(_) => "io error",
             ErrorKind::MultipleResults(_) => "multiple results returned",
+            ErrorKind::Network(_) => "network error",
             ErrorKind::NotAFile(_) => "not a file",
             ErrorKind::NoResults => "no results returned",
-            ErrorKind::PlistError(_) => "an error occurred loading a plist",
+            ErrorKind::Parse { .. } => "an error occurred parsing a playlist",
+            ErrorKind::PlistError { .. } => "an error occurred loading a plist",
+            ErrorKind::PlaylistChanged(_) => "playlist changed",
+            ErrorKind::RateLimitExhausted(_) => "rate limit retries exhausted",
             ErrorKind::Unauthorized => "unauthorized api call",
         }
     }
+
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.source
+            .as_ref()
+            .map(|source| source.as_ref() as &(dyn error::Error + 'static))
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref context) = self.context {
+            write!(f, "{}: ", context)?;
+        }
         match self.kind {
+            ErrorKind::ApiStatus {
+                status,
+                body: Some(ref body),
+            } => write!(
+                f,
+                "spotify api call failed with status code {}: {}",
+                status, body
+            ),
+            ErrorKind::ApiStatus { status, body: None } => {
+                write!(f, "spotify api call failed with status code {}", status)
+            }
             ErrorKind::AuthenticationFailure(ref s) => write!(f, "{}", s),
+            ErrorKind::Config(ref s) => write!(f, "{}", s),
             ErrorKind::FileNotFound(ref s) => write!(f, "{}", s),
+            ErrorKind::Io(ref path) => write!(f, "io error accessing {}", path),
             ErrorKind::MultipleResults(count) => write!(f, "expected 1 result, found {}", count),
+            ErrorKind::Network(ref s) => write!(f, "{}", s),
             ErrorKind::NotAFile(ref s) => write!(f, "{}", s),
             ErrorKind::NoResults => write!(f, "no results returned"),
-            ErrorKind::PlistError(ref s) => write!(f, "{}", s),
+            ErrorKind::Parse {
+                ref path,
+                ref detail,
+            } => write!(f, "failed to parse {}: {}", path, detail),
+            ErrorKind::PlistError {
+                ref path,
+                ref detail,
+            } => write!(f, "failed to load plist {}: {}", path, detail),
+            ErrorKind::PlaylistChanged(ref playlist) => write!(
+                f,
+                "playlist {} changed while it was read, try again",
+                playlist
+            ),
+            ErrorKind::RateLimitExhausted(retries) => write!(
+                f,
+                "spotify rate limit still in effect after {} retries",
+                retries
+            ),
             ErrorKind::Unauthorized => write!(f, "unauthorized api call"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ErrorKind};
+    use std::error::Error as StdError;
+    use std::io;
+
+    #[test]
+    fn test_display_includes_context() {
+        let error = Error::new(ErrorKind::NoResults).context("Matching track Yellow");
+        assert_eq!(
+            error.to_string(),
+            "Matching track Yellow: no results returned"
+        );
+    }
+
+    #[test]
+    fn test_source_is_preserved() {
+        let io_error = io::Error::new(io::ErrorKind::PermissionDenied, "permission denied");
+        let error = Error::with_source(ErrorKind::Io("/tmp/playlist.xml".to_string()), io_error);
+        assert_eq!(error.source().unwrap().to_string(), "permission denied");
+        assert_eq!(error.exit_code(), super::EXIT_IO_ERROR);
+    }
+
+    #[test]
+    fn test_api_status_display() {
+        let error = Error::new(ErrorKind::ApiStatus {
+            status: 404,
+            body: Some("Not found".to_string()),
+        });
+        assert_eq!(
+            error.to_string(),
+            "spotify api call failed with status code 404: Not found"
+        );
+        assert_eq!(error.exit_code(), super::EXIT_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,310 @@
+//! Writing iTunes tracks to playlist formats other players understand.
+use crate::error::{Error, ErrorKind};
+use crate::itunes::location::encode_location;
+use crate::itunes::Track;
+use quick_xml::escape::escape;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A playlist file format tracks can be exported to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Csv,
+    M3u8,
+    Xspf,
+}
+
+impl Format {
+    /// Names accepted on the command line.
+    pub const NAMES: &'static [&'static str] = &["csv", "m3u8", "xspf"];
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "m3u8" => Ok(Format::M3u8),
+            "xspf" => Ok(Format::Xspf),
+            _ => Err(Error::new(ErrorKind::Config(format!(
+                "unknown export format {}",
+                s
+            )))),
+        }
+    }
+}
+
+/// A track to export, with its resolved local path if it has one.
+pub struct Entry<'a> {
+    pub track: &'a Track,
+    pub path: Option<PathBuf>,
+}
+
+impl<'a> Entry<'a> {
+    /// Where players should find the track: the local path, or the original location otherwise.
+    fn location(&self) -> Option<String> {
+        match self.path {
+            Some(ref path) => Some(path.to_string_lossy().into_owned()),
+            None => self.track.location.clone(),
+        }
+    }
+
+    /// The location as a URL, as XSPF requires.
+    fn location_url(&self) -> Option<String> {
+        match self.path {
+            Some(ref path) => Some(encode_location(path)),
+            None => self.track.location.clone(),
+        }
+    }
+}
+
+/// Write the tracks in the given format.
+pub fn write<W: Write>(
+    format: Format,
+    title: &str,
+    entries: &[Entry],
+    out: &mut W,
+) -> io::Result<()> {
+    match format {
+        Format::Csv => write_csv(entries, out),
+        Format::M3u8 => write_m3u8(title, entries, out),
+        Format::Xspf => write_xspf(title, entries, out),
+    }
+}
+
+fn write_m3u8<W: Write>(title: &str, entries: &[Entry], out: &mut W) -> io::Result<()> {
+    writeln!(out, "#EXTM3U")?;
+    writeln!(out, "#PLAYLIST:{}", single_line(title))?;
+    for entry in entries {
+        let location = match entry.location() {
+            Some(location) => location,
+            None => continue,
+        };
+        let seconds = entry.track.total_time.map_or(-1, |ms| (ms / 1000) as i64);
+        let display = match entry.track.artist {
+            Some(ref artist) => format!("{} - {}", artist, entry.track.name),
+            None => entry.track.name.clone(),
+        };
+        writeln!(out, "#EXTINF:{},{}", seconds, single_line(&display))?;
+        writeln!(out, "{}", location)?;
+    }
+    Ok(())
+}
+
+fn single_line(s: &str) -> String {
+    s.replace(['\r', '\n'], " ")
+}
+
+fn write_xspf<W: Write>(title: &str, entries: &[Entry], out: &mut W) -> io::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<playlist version="1" xmlns="http://xspf.org/ns/0/">"#
+    )?;
+    writeln!(out, "  <title>{}</title>", escape(title))?;
+    writeln!(out, "  <trackList>")?;
+    for entry in entries {
+        let track = entry.track;
+        writeln!(out, "    <track>")?;
+        if let Some(location) = entry.location_url() {
+            writeln!(out, "      <location>{}</location>", escape(&location))?;
+        }
+        writeln!(out, "      <title>{}</title>", escape(&track.name))?;
+        if let Some(ref artist) = track.artist {
+            writeln!(out, "      <creator>{}</creator>", escape(artist))?;
+        }
+        if let Some(ref album) = track.album {
+            writeln!(out, "      <album>{}</album>", escape(album))?;
+        }
+        if let Some(track_number) = track.track_number {
+            writeln!(out, "      <trackNum>{}</trackNum>", track_number)?;
+        }
+        if let Some(total_time) = track.total_time {
+            writeln!(out, "      <duration>{}</duration>", total_time)?;
+        }
+        writeln!(out, "    </track>")?;
+    }
+    writeln!(out, "  </trackList>")?;
+    writeln!(out, "</playlist>")
+}
+
+const CSV_COLUMNS: &[&str] = &[
+    "Track ID",
+    "Persistent ID",
+    "Name",
+    "Artist",
+    "Album Artist",
+    "Composer",
+    "Album",
+    "Genre",
+    "Kind",
+    "Year",
+    "Total Time",
+    "Track Number",
+    "Track Count",
+    "Disc Number",
+    "Disc Count",
+    "Compilation",
+    "Location",
+    "Path",
+    "Play Count",
+    "Skip Count",
+    "Rating",
+    "Loved",
+    "Disliked",
+    "Date Added",
+    "Track Type",
+    "Podcast",
+    "Movie",
+    "TV Show",
+    "Music Video",
+];
+
+fn write_csv<W: Write>(entries: &[Entry], out: &mut W) -> io::Result<()> {
+    write_csv_row(out, CSV_COLUMNS.iter().map(|column| column.to_string()))?;
+    for entry in entries {
+        let track = entry.track;
+        let fields = vec![
+            optional(track.track_id),
+            track.persistent_id.clone().unwrap_or_default(),
+            track.name.clone(),
+            track.artist.clone().unwrap_or_default(),
+            track.album_artist.clone().unwrap_or_default(),
+            track.composer.clone().unwrap_or_default(),
+            track.album.clone().unwrap_or_default(),
+            track.genre.clone().unwrap_or_default(),
+            track.kind.clone().unwrap_or_default(),
+            optional(track.year),
+            optional(track.total_time),
+            optional(track.track_number),
+            optional(track.track_count),
+            optional(track.disc_number),
+            optional(track.disc_count),
+            track.compilation.to_string(),
+            track.location.clone().unwrap_or_default(),
+            entry
+                .path
+                .as_ref()
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            optional(track.play_count),
+            optional(track.skip_count),
+            optional(track.rating),
+            track.loved.to_string(),
+            track.disliked.to_string(),
+            optional(track.date_added.map(|date| date.to_rfc3339())),
+            track.track_type.clone().unwrap_or_default(),
+            track.podcast.to_string(),
+            track.movie.to_string(),
+            track.tv_show.to_string(),
+            track.music_video.to_string(),
+        ];
+        write_csv_row(out, fields.into_iter())?;
+    }
+    Ok(())
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn write_csv_row<W, I>(out: &mut W, fields: I) -> io::Result<()>
+where
+    W: Write,
+    I: Iterator<Item = String>,
+{
+    let fields: Vec<String> = fields.map(|field| csv_field(&field)).collect();
+    write!(out, "{}\r\n", fields.join(","))
+}
+
+/// Quote a field if it contains a separator, quote or line break, as RFC 4180 describes.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write, Entry, Format};
+    use crate::itunes::{test_track, Track};
+    use std::path::PathBuf;
+
+    fn track(name: &str, artist: &str, location: &str) -> Track {
+        test_track(&[
+            ("Track ID", 7.into()),
+            ("Name", name.into()),
+            ("Artist", artist.into()),
+            ("Total Time", 215_500.into()),
+            ("Location", location.into()),
+        ])
+    }
+
+    fn export(format: Format, entries: &[Entry]) -> String {
+        let mut out = Vec::new();
+        write(format, "Road Trip", entries, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_m3u8_uses_resolved_paths() {
+        let local = track(
+            "Glosóli",
+            "Sigur Rós",
+            "file://localhost/Music/Glos%C3%B3li.m4a",
+        );
+        let stream = track("Radio", "BBC", "http://radio.example.com/stream");
+        let entries = vec![
+            Entry {
+                track: &local,
+                path: Some(PathBuf::from("/mnt/nas/Glosóli.m4a")),
+            },
+            Entry {
+                track: &stream,
+                path: None,
+            },
+        ];
+        assert_eq!(
+            export(Format::M3u8, &entries),
+            "#EXTM3U\n#PLAYLIST:Road Trip\n\
+             #EXTINF:215,Sigur Rós - Glosóli\n/mnt/nas/Glosóli.m4a\n\
+             #EXTINF:215,BBC - Radio\nhttp://radio.example.com/stream\n"
+        );
+    }
+
+    #[test]
+    fn test_xspf_escapes_and_encodes() {
+        let track = track("Rock & Roll", "Led Zeppelin", "file:///Music/Rock.mp3");
+        let entries = vec![Entry {
+            track: &track,
+            path: Some(PathBuf::from("/mnt/nas/Rock & Roll.mp3")),
+        }];
+        let xspf = export(Format::Xspf, &entries);
+        assert!(xspf.contains("<location>file:///mnt/nas/Rock%20%26%20Roll.mp3</location>"));
+        assert!(xspf.contains("<title>Rock &amp; Roll</title>"));
+        assert!(xspf.contains("<duration>215500</duration>"));
+    }
+
+    #[test]
+    fn test_csv_quotes_fields() {
+        let track = track("Hello, \"World\"", "Artist", "file:///Music/Hello.mp3");
+        let entries = vec![Entry {
+            track: &track,
+            path: None,
+        }];
+        let csv = export(Format::Csv, &entries);
+        let mut lines = csv.split("\r\n");
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("Track ID,Persistent ID,Name,Artist,"));
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("7,,\"Hello, \"\"World\"\"\",Artist,"));
+    }
+}
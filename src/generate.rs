@@ -0,0 +1,219 @@
+//! Playlists generated from the play statistics of an iTunes library, e.g. the most played tracks.
+use crate::error::{Error, ErrorKind};
+use crate::itunes::Track;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use std::cmp::Reverse;
+use std::str::FromStr;
+
+/// How the tracks of a generated playlist are chosen and ordered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    /// Played tracks, most played first.
+    MostPlayed,
+    /// Played tracks that were never skipped, most played first.
+    NeverSkipped,
+    /// Tracks by the date they were added to the library, newest first.
+    RecentlyAdded,
+    /// Played tracks by the date they were last played, most recent first.
+    RecentlyPlayed,
+}
+
+impl Rule {
+    /// Names accepted on the command line.
+    pub const NAMES: &'static [&'static str] = &[
+        "most-played",
+        "never-skipped",
+        "recently-added",
+        "recently-played",
+    ];
+
+    /// Name of the Spotify playlist, e.g. `Top 100 most played`.
+    pub fn playlist_name(self, limit: Option<usize>) -> String {
+        match (self, limit) {
+            (Rule::MostPlayed, Some(limit)) => format!("Top {} most played", limit),
+            (Rule::MostPlayed, None) => "Most played".to_string(),
+            (Rule::NeverSkipped, _) => "Never skipped".to_string(),
+            (Rule::RecentlyAdded, _) => "Recently added".to_string(),
+            (Rule::RecentlyPlayed, _) => "Recently played".to_string(),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "most-played" => Ok(Rule::MostPlayed),
+            "never-skipped" => Ok(Rule::NeverSkipped),
+            "recently-added" => Ok(Rule::RecentlyAdded),
+            "recently-played" => Ok(Rule::RecentlyPlayed),
+            _ => Err(Error::new(ErrorKind::Config(format!("unknown rule {}", s)))),
+        }
+    }
+}
+
+/// Conditions every track of a generated playlist has to meet, whatever the rule.
+#[derive(Clone, Debug, Default)]
+pub struct Filters {
+    pub min_plays: Option<u32>,
+    pub max_skips: Option<u32>,
+    pub added_after: Option<DateTime<Utc>>,
+    pub played_after: Option<DateTime<Utc>>,
+    /// Minimum rating in stars.
+    pub min_rating: Option<u8>,
+}
+
+impl Filters {
+    fn matches(&self, track: &Track) -> bool {
+        let plays = track.play_count.unwrap_or(0);
+        let skips = track.skip_count.unwrap_or(0);
+        self.min_plays.is_none_or(|min_plays| plays >= min_plays)
+            && self.max_skips.is_none_or(|max_skips| skips <= max_skips)
+            && self
+                .added_after
+                .is_none_or(|after| track.date_added.is_some_and(|added| added >= after))
+            && self
+                .played_after
+                .is_none_or(|after| track.play_date.is_some_and(|played| played >= after))
+            && self
+                .min_rating
+                .is_none_or(|min_rating| track.stars().is_some_and(|stars| stars >= min_rating))
+    }
+}
+
+/// Choose the tracks of a playlist generated by `rule`, as indices into `tracks` in playlist
+/// order. Every track the rule applies to is chosen, to be cut to the limit once matched.
+///
+/// Ties keep the order of `tracks`.
+pub fn select(tracks: &[&Track], rule: Rule, filters: &Filters) -> Vec<usize> {
+    let plays = |i: usize| tracks[i].play_count.unwrap_or(0);
+    let mut selected: Vec<usize> = (0..tracks.len())
+        .filter(|&i| filters.matches(tracks[i]))
+        .filter(|&i| match rule {
+            Rule::MostPlayed | Rule::RecentlyPlayed => plays(i) > 0,
+            Rule::NeverSkipped => plays(i) > 0 && tracks[i].skip_count.unwrap_or(0) == 0,
+            Rule::RecentlyAdded => tracks[i].date_added.is_some(),
+        })
+        .collect();
+    match rule {
+        Rule::MostPlayed | Rule::NeverSkipped => selected.sort_by_key(|&i| Reverse(plays(i))),
+        Rule::RecentlyAdded => selected.sort_by_key(|&i| Reverse(tracks[i].date_added)),
+        Rule::RecentlyPlayed => selected.sort_by_key(|&i| Reverse(tracks[i].play_date)),
+    }
+    selected
+}
+
+/// Parse a date given on the command line as `YYYY-MM-DD`, taken as midnight UTC.
+pub fn parse_date(s: &str) -> Result<DateTime<Utc>, Error> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| Utc.from_utc_datetime(&date))
+        .ok_or_else(|| {
+            Error::new(ErrorKind::Config(format!(
+                "invalid date {}, expected YYYY-MM-DD",
+                s
+            )))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_date, select, Filters, Rule};
+    use crate::itunes::{test_track, Track};
+    use plist::{Date, Value};
+
+    fn track(name: &str, plays: u32, skips: u32, added: &str, played: Option<&str>) -> Track {
+        let date = |day: &str| -> Value {
+            Date::from_xml_format(&format!("{}T12:00:00Z", day))
+                .unwrap()
+                .into()
+        };
+        let mut entries = vec![
+            ("Name", name.into()),
+            ("Play Count", i64::from(plays).into()),
+            ("Skip Count", i64::from(skips).into()),
+            ("Date Added", date(added)),
+        ];
+        if let Some(played) = played {
+            entries.push(("Play Date UTC", date(played)));
+        }
+        test_track(&entries)
+    }
+
+    fn names<'a>(tracks: &[&'a Track], selected: Vec<usize>) -> Vec<&'a str> {
+        selected
+            .into_iter()
+            .map(|i| tracks[i].name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let tracks = [
+            track("Yellow", 12, 3, "2018-01-01", Some("2019-05-01")),
+            track("Clocks", 40, 0, "2019-06-01", Some("2019-06-02")),
+            track("Fix You", 0, 0, "2020-01-01", None),
+            track("Trouble", 12, 0, "2017-01-01", Some("2019-01-01")),
+        ];
+        let tracks: Vec<&Track> = tracks.iter().collect();
+        let no_filters = Filters::default();
+
+        assert_eq!(
+            names(&tracks, select(&tracks, Rule::MostPlayed, &no_filters)),
+            vec!["Clocks", "Yellow", "Trouble"]
+        );
+        assert_eq!(
+            names(&tracks, select(&tracks, Rule::NeverSkipped, &no_filters)),
+            vec!["Clocks", "Trouble"]
+        );
+        assert_eq!(
+            names(&tracks, select(&tracks, Rule::RecentlyAdded, &no_filters)),
+            vec!["Fix You", "Clocks", "Yellow", "Trouble"]
+        );
+        assert_eq!(
+            names(&tracks, select(&tracks, Rule::RecentlyPlayed, &no_filters)),
+            vec!["Clocks", "Yellow", "Trouble"]
+        );
+
+        let filters = Filters {
+            min_plays: Some(20),
+            ..Filters::default()
+        };
+        assert_eq!(
+            names(&tracks, select(&tracks, Rule::MostPlayed, &filters)),
+            vec!["Clocks"]
+        );
+        let filters = Filters {
+            added_after: Some(parse_date("2018-01-01").unwrap()),
+            ..Filters::default()
+        };
+        assert_eq!(
+            names(&tracks, select(&tracks, Rule::MostPlayed, &filters)),
+            vec!["Clocks", "Yellow"]
+        );
+    }
+
+    #[test]
+    fn test_playlist_name() {
+        assert_eq!(
+            Rule::MostPlayed.playlist_name(Some(100)),
+            "Top 100 most played"
+        );
+        assert_eq!(Rule::MostPlayed.playlist_name(None), "Most played");
+        assert_eq!(
+            Rule::RecentlyAdded.playlist_name(Some(50)),
+            "Recently added"
+        );
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(
+            parse_date("2019-10-01").unwrap().to_rfc3339(),
+            "2019-10-01T00:00:00+00:00"
+        );
+        assert!(parse_date("01/10/2019").is_err());
+    }
+}
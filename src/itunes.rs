@@ -1,55 +1,644 @@
 use crate::error::{Error, ErrorKind};
 use crate::types;
+use crate::util;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use plist::{Dictionary, Value};
 use serde::Deserialize;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
-use std::path::Path;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::str::FromStr;
+use std::time::SystemTime;
+use zip::ZipArchive;
 
+pub mod location;
+pub mod stream;
+
+/// Path that makes `load_playlist` read the playlist from stdin.
+pub const STDIN_PATH: &str = "-";
+
+/// Load an iTunes playlist or library from a file, or from stdin if `path` is `-`.
+///
+/// XML and binary plists are supported, optionally compressed with gzip or inside a zip archive.
 pub fn load_playlist(path: &str) -> Result<Playlist, Error> {
-    let p = Path::new(path);
-    if !p.exists() {
-        return Err(Error::new(ErrorKind::FileNotFound(
-            "Can't find playlist file".to_string(),
-        )));
+    let bytes = util::read_input(path)?;
+    parse_playlist(path, &bytes)
+}
+
+/// Parse a playlist from the raw (possibly compressed) contents of a file.
+fn parse_playlist(path: &str, bytes: &[u8]) -> Result<Playlist, Error> {
+    let (compression, data) = decompress(path, bytes)?;
+    let format = PlistFormat::detect(&data);
+    debug!(
+        "loading {} as {}",
+        path,
+        describe_format(compression, format)
+    );
+
+    plist::from_bytes(&data).map_err(|e| plist_error(path, &data, compression, format, e))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Compression {
+    None,
+    Gzip,
+    Zip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlistFormat {
+    Xml,
+    Binary,
+    Unknown,
+}
+
+impl PlistFormat {
+    fn detect(data: &[u8]) -> PlistFormat {
+        if data.starts_with(b"bplist") {
+            return PlistFormat::Binary;
+        }
+        let text = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+        let start = text.iter().position(|b| !b.is_ascii_whitespace());
+        match start.map(|i| &text[i..]) {
+            Some(t) if t.starts_with(b"<?xml") || t.starts_with(b"<plist") => PlistFormat::Xml,
+            _ => PlistFormat::Unknown,
+        }
+    }
+}
+
+fn describe_format(compression: Compression, format: PlistFormat) -> String {
+    let format = match format {
+        PlistFormat::Xml => "XML plist",
+        PlistFormat::Binary => "binary plist",
+        PlistFormat::Unknown => "file of unknown format",
+    };
+    match compression {
+        Compression::None => format.to_string(),
+        Compression::Gzip => format!("gzip-compressed {}", format),
+        Compression::Zip => format!("{} from a zip archive", format),
+    }
+}
+
+/// Unpack gzip and zip compressed exports, returning other data unchanged.
+fn decompress<'a>(path: &str, bytes: &'a [u8]) -> Result<(Compression, Cow<'a, [u8]>), Error> {
+    let corrupt = |kind: &str, detail: String| {
+        Error::new(ErrorKind::PlistError {
+            path: path.to_string(),
+            detail: format!("could not decompress {} data: {}", kind, detail),
+        })
+    };
+
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut data = Vec::new();
+        GzDecoder::new(bytes)
+            .read_to_end(&mut data)
+            .map_err(|e| corrupt("gzip", e.to_string()))?;
+        return Ok((Compression::Gzip, Cow::Owned(data)));
     }
-    if !p.is_file() {
-        return Err(Error::new(ErrorKind::NotAFile(
-            "Expected a playlist file, found a directory".to_string(),
-        )));
+
+    if bytes.starts_with(b"PK\x03\x04") {
+        let mut archive =
+            ZipArchive::new(Cursor::new(bytes)).map_err(|e| corrupt("zip", e.to_string()))?;
+        let names: Vec<String> = archive
+            .file_names()
+            .filter(|name| !name.starts_with("__MACOSX/") && !name.ends_with('/'))
+            .map(str::to_string)
+            .collect();
+        let name = names
+            .iter()
+            .find(|name| {
+                let name = name.to_lowercase();
+                name.ends_with(".xml") || name.ends_with(".plist")
+            })
+            .or_else(|| {
+                if names.len() == 1 {
+                    names.first()
+                } else {
+                    None
+                }
+            })
+            .ok_or_else(|| corrupt("zip", "no playlist found in the archive".to_string()))?;
+        let mut data = Vec::new();
+        archive
+            .by_name(name)
+            .map_err(|e| corrupt("zip", e.to_string()))?
+            .read_to_end(&mut data)
+            .map_err(|e| corrupt("zip", e.to_string()))?;
+        return Ok((Compression::Zip, Cow::Owned(data)));
     }
 
-    let playlist: Playlist = plist::from_file(&path)?;
-    Ok(playlist)
+    Ok((Compression::None, Cow::Borrowed(bytes)))
 }
 
-impl From<plist::Error> for Error {
-    fn from(_: plist::Error) -> Self {
-        // TODO: Get actual error instead of hard coding a placeholder.
-        Error::new(ErrorKind::PlistError("Failed to load plist".to_string()))
+/// Convert a plist error into an `Error`, keeping the file path, the detected format and the
+/// position of the failure.
+fn plist_error(
+    path: &str,
+    data: &[u8],
+    compression: Compression,
+    format: PlistFormat,
+    error: plist::Error,
+) -> Error {
+    let mut detail = format!(
+        "could not parse {}: {}",
+        describe_format(compression, format),
+        error
+    );
+    if format == PlistFormat::Xml {
+        if let Some(line) = error_offset(&detail).map(|offset| line_at_offset(data, offset)) {
+            detail = format!("{} at line {}", detail, line);
+        }
     }
+    Error::with_source(
+        ErrorKind::PlistError {
+            path: path.to_string(),
+            detail,
+        },
+        error,
+    )
+}
+
+/// Extract the byte offset plist appends to its error messages, e.g. `... (offset 1024)`.
+fn error_offset(message: &str) -> Option<u64> {
+    let start = message.rfind("(offset ")? + "(offset ".len();
+    message[start..].trim_end_matches(')').parse().ok()
+}
+
+/// Translate a byte offset into a 1-based line number.
+fn line_at_offset(data: &[u8], offset: u64) -> usize {
+    let end = usize::try_from(offset).map_or(data.len(), |offset| offset.min(data.len()));
+    data[..end].iter().filter(|&&b| b == b'\n').count() + 1
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(from = "RawPlaylist")]
 pub struct Playlist {
+    pub major_version: Option<i32>,
+    pub music_folder: Option<String>,
+    pub tracks: BTreeMap<String, Track>,
+    /// Playlists and playlist folders, in the order iTunes lists them.
+    pub playlists: Vec<LibraryPlaylist>,
+    /// Tracks that couldn't be parsed and were left out of `tracks`.
+    pub warnings: Vec<TrackWarning>,
+}
+
+impl Playlist {
+    /// The tracks of a playlist in playlist order, skipping items missing from `tracks`.
+    pub fn playlist_tracks(&self, playlist: &LibraryPlaylist) -> Vec<&Track> {
+        playlist
+            .track_ids
+            .iter()
+            .filter_map(|id| self.tracks.get(&id.to_string()))
+            .collect()
+    }
+
+    /// Names of the folders containing a playlist, outermost first.
+    pub fn folder_path(&self, playlist: &LibraryPlaylist) -> Vec<&str> {
+        let mut path = Vec::new();
+        let mut parent = playlist.parent_persistent_id.as_ref();
+        // Bound the walk by the number of playlists in case the parents form a cycle.
+        while let Some(parent_id) = parent {
+            if path.len() >= self.playlists.len() {
+                break;
+            }
+            match self
+                .playlists
+                .iter()
+                .find(|p| p.persistent_id.as_ref() == Some(parent_id))
+            {
+                Some(folder) => {
+                    path.push(folder.name.as_str());
+                    parent = folder.parent_persistent_id.as_ref();
+                }
+                None => break,
+            }
+        }
+        path.reverse();
+        path
+    }
+
+    /// The playlist with this name or qualified name, skipping folders.
+    pub fn find_playlist(&self, name: &str, separator: &str) -> Option<&LibraryPlaylist> {
+        self.playlists
+            .iter()
+            .filter(|p| !p.folder)
+            .find(|p| p.name == name || self.qualified_name(p, separator) == name)
+    }
+
+    /// Every track in iTunes order.
+    ///
+    /// A playlist export holds a single playlist whose order is used. For anything else, e.g. a
+    /// whole library, tracks are ordered by track ID.
+    pub fn tracks_in_order(&self) -> Vec<&Track> {
+        if let [playlist] = self.playlists.as_slice() {
+            return self.playlist_tracks(playlist);
+        }
+        let mut tracks: Vec<&Track> = self.tracks.values().collect();
+        tracks.sort_by_key(|track| track.track_id);
+        tracks
+    }
+
+    /// The playlist name prefixed with its folders, e.g. `Workout / Running`.
+    pub fn qualified_name(&self, playlist: &LibraryPlaylist, separator: &str) -> String {
+        let mut names = self.folder_path(playlist);
+        names.push(&playlist.name);
+        names.join(separator)
+    }
+}
+
+/// A playlist, smart playlist or playlist folder from the `Playlists` array of a library.
+#[derive(Clone, Debug)]
+pub struct LibraryPlaylist {
+    pub name: String,
+    pub playlist_id: Option<i64>,
+    pub persistent_id: Option<String>,
+    pub parent_persistent_id: Option<String>,
+    pub folder: bool,
+    /// Whether the playlist is a smart playlist, i.e. it has `Smart Info` or `Smart Criteria`.
+    pub smart: bool,
+    /// Whether this is the master `Library` playlist.
+    pub master: bool,
+    /// Set for playlists iTunes creates itself, e.g. Music, Movies or Downloaded.
+    pub distinguished_kind: Option<i64>,
+    pub visible: bool,
+    pub track_ids: Vec<i64>,
+}
+
+impl LibraryPlaylist {
+    /// Whether iTunes created the playlist rather than the user.
+    pub fn is_builtin(&self) -> bool {
+        self.master || self.distinguished_kind.is_some() || !self.visible
+    }
+}
+
+impl TryFrom<Dictionary> for LibraryPlaylist {
+    type Error = String;
+
+    fn try_from(mut dict: Dictionary) -> Result<Self, Self::Error> {
+        let name = take_string(&mut dict, "Name")?.ok_or("missing field `Name`")?;
+        let smart = dict.contains_key("Smart Info") || dict.contains_key("Smart Criteria");
+        let track_ids = match dict.remove("Playlist Items") {
+            None => Vec::new(),
+            Some(Value::Array(items)) => items
+                .into_iter()
+                .filter_map(|item| match item {
+                    Value::Dictionary(mut item) => take_integer(&mut item, "Track ID").transpose(),
+                    _ => None,
+                })
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err("field `Playlist Items` should be an array".to_string()),
+        };
+        Ok(LibraryPlaylist {
+            name,
+            playlist_id: take_integer(&mut dict, "Playlist ID")?,
+            persistent_id: take_string(&mut dict, "Playlist Persistent ID")?,
+            parent_persistent_id: take_string(&mut dict, "Parent Persistent ID")?,
+            folder: take_bool(&mut dict, "Folder")?,
+            smart,
+            master: take_bool(&mut dict, "Master")?,
+            distinguished_kind: take_integer(&mut dict, "Distinguished Kind")?,
+            visible: take_bool_or(&mut dict, "Visible", true)?,
+            track_ids,
+        })
+    }
+}
+
+/// The playlist as it appears in the plist, before individual tracks are validated.
+#[derive(Deserialize)]
+struct RawPlaylist {
     #[serde(rename = "Major Version")]
-    major_version: i32,
+    major_version: Option<i32>,
     #[serde(rename = "Music Folder")]
-    pub music_folder: String,
-    #[serde(rename = "Tracks")]
-    pub tracks: BTreeMap<String, Track>,
+    music_folder: Option<String>,
+    #[serde(rename = "Tracks", default)]
+    tracks: BTreeMap<String, Value>,
+    #[serde(rename = "Playlists", default)]
+    playlists: Vec<Value>,
 }
 
+impl From<RawPlaylist> for Playlist {
+    fn from(raw: RawPlaylist) -> Self {
+        let mut playlist = Playlist {
+            major_version: raw.major_version,
+            music_folder: raw.music_folder,
+            tracks: BTreeMap::new(),
+            playlists: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        for (key, value) in raw.tracks {
+            playlist.insert_track(key, value);
+        }
+        for value in raw.playlists {
+            playlist.insert_playlist(value);
+        }
+
+        playlist
+    }
+}
+
+impl Playlist {
+    /// Add a track from its plist value, recording a warning if the entry is malformed.
+    fn insert_track(&mut self, key: String, value: Value) {
+        let dict = match value {
+            Value::Dictionary(dict) => dict,
+            _ => {
+                self.warnings.push(TrackWarning {
+                    key,
+                    name: None,
+                    message: "expected a dictionary".to_string(),
+                });
+                return;
+            }
+        };
+        let name = dict
+            .get("Name")
+            .and_then(Value::as_string)
+            .map(str::to_string);
+        match Track::try_from(dict) {
+            Ok(track) => {
+                self.tracks.insert(key, track);
+            }
+            Err(message) => {
+                warn!("skipping track {}: {}", key, message);
+                self.warnings.push(TrackWarning { key, name, message });
+            }
+        }
+    }
+
+    /// Add a playlist from its plist value, skipping it if the entry is malformed.
+    fn insert_playlist(&mut self, value: Value) {
+        let result = match value {
+            Value::Dictionary(dict) => LibraryPlaylist::try_from(dict),
+            _ => Err("expected a dictionary".to_string()),
+        };
+        match result {
+            Ok(playlist) => self.playlists.push(playlist),
+            Err(message) => warn!("skipping playlist: {}", message),
+        }
+    }
+}
+
+/// A track that was skipped because its entry in the plist is malformed.
+#[derive(Clone, Debug)]
+pub struct TrackWarning {
+    /// The key of the track in the `Tracks` dictionary.
+    pub key: String,
+    pub name: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for TrackWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "track {} ({}): {}", self.key, name, self.message),
+            None => write!(f, "track {}: {}", self.key, self.message),
+        }
+    }
+}
+
+/// A track from an iTunes library or playlist export.
+///
+/// Keys that aren't modelled explicitly are kept in `extra` so nothing from the export is lost.
 #[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "Dictionary")]
 pub struct Track {
-    #[serde(rename = "Name")]
+    pub track_id: Option<i64>,
+    pub persistent_id: Option<String>,
     pub name: String,
-    #[serde(rename = "Artist")]
-    pub artist: String,
-    #[serde(rename = "Album")]
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub composer: Option<String>,
     pub album: Option<String>,
-    #[serde(rename = "Year")]
-    pub year: Option<i16>,
-    #[serde(rename = "Genre")]
     pub genre: Option<String>,
+    pub kind: Option<String>,
+    pub year: Option<i16>,
+    /// Duration of the track in milliseconds.
+    pub total_time: Option<u64>,
+    pub track_number: Option<u32>,
+    pub track_count: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub disc_count: Option<u32>,
+    pub compilation: bool,
+    /// Percent-encoded `file://` URL of the audio file.
+    pub location: Option<String>,
+    pub play_count: Option<u32>,
+    /// When the track was last played.
+    pub play_date: Option<DateTime<Utc>>,
+    pub skip_count: Option<u32>,
+    /// Rating from 0 to 100, where every 20 points is one star.
+    pub rating: Option<u8>,
+    /// Whether `rating` is taken from the album rating rather than set on the track.
+    pub rating_computed: bool,
+    pub loved: bool,
+    pub disliked: bool,
+    pub date_added: Option<DateTime<Utc>>,
+    /// Where the track is stored, e.g. `File`, `Remote` or `URL`.
+    pub track_type: Option<String>,
+    pub podcast: bool,
+    pub movie: bool,
+    pub tv_show: bool,
+    pub music_video: bool,
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl TryFrom<Dictionary> for Track {
+    type Error = String;
+
+    fn try_from(mut dict: Dictionary) -> Result<Self, Self::Error> {
+        let name = take_string(&mut dict, "Name")?.ok_or("missing field `Name`")?;
+        Ok(Track {
+            track_id: take_integer(&mut dict, "Track ID")?,
+            persistent_id: take_string(&mut dict, "Persistent ID")?,
+            name,
+            artist: take_string(&mut dict, "Artist")?,
+            album_artist: take_string(&mut dict, "Album Artist")?,
+            composer: take_string(&mut dict, "Composer")?,
+            album: take_string(&mut dict, "Album")?,
+            genre: take_string(&mut dict, "Genre")?,
+            kind: take_string(&mut dict, "Kind")?,
+            year: take_integer(&mut dict, "Year")?,
+            total_time: take_integer(&mut dict, "Total Time")?,
+            track_number: take_integer(&mut dict, "Track Number")?,
+            track_count: take_integer(&mut dict, "Track Count")?,
+            disc_number: take_integer(&mut dict, "Disc Number")?,
+            disc_count: take_integer(&mut dict, "Disc Count")?,
+            compilation: take_bool(&mut dict, "Compilation")?,
+            location: take_string(&mut dict, "Location")?,
+            play_count: take_integer(&mut dict, "Play Count")?,
+            play_date: take_date(&mut dict, "Play Date UTC")?,
+            skip_count: take_integer(&mut dict, "Skip Count")?,
+            rating: take_integer(&mut dict, "Rating")?,
+            rating_computed: take_bool(&mut dict, "Rating Computed")?,
+            loved: take_bool(&mut dict, "Loved")?,
+            disliked: take_bool(&mut dict, "Disliked")?,
+            date_added: take_date(&mut dict, "Date Added")?,
+            track_type: take_string(&mut dict, "Track Type")?,
+            podcast: take_bool(&mut dict, "Podcast")?,
+            movie: take_bool(&mut dict, "Movie")?,
+            tv_show: take_bool(&mut dict, "TV Show")?,
+            music_video: take_bool(&mut dict, "Music Video")?,
+            extra: dict.into_iter().collect(),
+        })
+    }
+}
+
+impl Track {
+    /// The rating set on the track in whole stars from 1 to 5, ignoring ratings taken from the
+    /// album.
+    pub fn stars(&self) -> Option<u8> {
+        match self.rating {
+            Some(rating) if !self.rating_computed => match (rating.min(100) + 10) / 20 {
+                0 => None,
+                stars => Some(stars),
+            },
+            _ => None,
+        }
+    }
+
+    /// Classify the item so non-music entries can be left out of a Spotify match.
+    pub fn media_kind(&self) -> MediaKind {
+        if self.podcast {
+            return MediaKind::Podcast;
+        }
+        if self.tv_show {
+            return MediaKind::TvShow;
+        }
+        if self.music_video {
+            return MediaKind::MusicVideo;
+        }
+        if self.movie {
+            return MediaKind::Movie;
+        }
+        if self.track_type.as_deref() == Some("URL") {
+            return MediaKind::Stream;
+        }
+        if self.genre.as_deref() == Some("Voice Memo") {
+            return MediaKind::VoiceMemo;
+        }
+
+        let kind = self.kind.as_deref().unwrap_or_default().to_lowercase();
+        if kind.contains("podcast") {
+            MediaKind::Podcast
+        } else if kind.contains("book") || kind.contains("audible") {
+            MediaKind::Audiobook
+        } else if kind.contains("pdf") {
+            MediaKind::Document
+        } else if kind.contains("stream") {
+            MediaKind::Stream
+        } else if kind.contains("video") || kind.contains("movie") {
+            MediaKind::Movie
+        } else {
+            MediaKind::Music
+        }
+    }
+}
+
+/// The type of media an iTunes library entry holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MediaKind {
+    Music,
+    Audiobook,
+    Document,
+    Movie,
+    MusicVideo,
+    Podcast,
+    Stream,
+    TvShow,
+    VoiceMemo,
+}
+
+impl MediaKind {
+    /// Names accepted on the command line for the non-music kinds.
+    pub const NON_MUSIC_NAMES: &'static [&'static str] = &[
+        "audiobook",
+        "document",
+        "movie",
+        "music-video",
+        "podcast",
+        "stream",
+        "tv-show",
+        "voice-memo",
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MediaKind::Music => "music",
+            MediaKind::Audiobook => "audiobook",
+            MediaKind::Document => "document",
+            MediaKind::Movie => "movie",
+            MediaKind::MusicVideo => "music-video",
+            MediaKind::Podcast => "podcast",
+            MediaKind::Stream => "stream",
+            MediaKind::TvShow => "tv-show",
+            MediaKind::VoiceMemo => "voice-memo",
+        }
+    }
+}
+
+impl FromStr for MediaKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "music" => Ok(MediaKind::Music),
+            "audiobook" => Ok(MediaKind::Audiobook),
+            "document" => Ok(MediaKind::Document),
+            "movie" => Ok(MediaKind::Movie),
+            "music-video" => Ok(MediaKind::MusicVideo),
+            "podcast" => Ok(MediaKind::Podcast),
+            "stream" => Ok(MediaKind::Stream),
+            "tv-show" => Ok(MediaKind::TvShow),
+            "voice-memo" => Ok(MediaKind::VoiceMemo),
+            _ => Err(Error::new(ErrorKind::Config(format!(
+                "unknown media kind {}",
+                s
+            )))),
+        }
+    }
+}
+
+fn take_string(dict: &mut Dictionary, key: &str) -> Result<Option<String>, String> {
+    match dict.remove(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(_) => Err(format!("field `{}` should be a string", key)),
+    }
+}
+
+fn take_integer<T: TryFrom<i64>>(dict: &mut Dictionary, key: &str) -> Result<Option<T>, String> {
+    match dict.remove(key) {
+        None => Ok(None),
+        Some(Value::Integer(i)) => i
+            .as_signed()
+            .and_then(|i| T::try_from(i).ok())
+            .map(Some)
+            .ok_or_else(|| format!("field `{}` is out of range", key)),
+        Some(_) => Err(format!("field `{}` should be an integer", key)),
+    }
+}
+
+fn take_bool(dict: &mut Dictionary, key: &str) -> Result<bool, String> {
+    take_bool_or(dict, key, false)
+}
+
+fn take_bool_or(dict: &mut Dictionary, key: &str, default: bool) -> Result<bool, String> {
+    match dict.remove(key) {
+        None => Ok(default),
+        Some(Value::Boolean(b)) => Ok(b),
+        Some(_) => Err(format!("field `{}` should be a boolean", key)),
+    }
+}
+
+fn take_date(dict: &mut Dictionary, key: &str) -> Result<Option<DateTime<Utc>>, String> {
+    match dict.remove(key) {
+        None => Ok(None),
+        Some(Value::Date(d)) => Ok(Some(SystemTime::from(d).into())),
+        Some(_) => Err(format!("field `{}` should be a date", key)),
+    }
 }
 
 impl types::DisplayTrack for Track {
@@ -58,7 +647,7 @@ impl types::DisplayTrack for Track {
     }
 
     fn artist(&self) -> String {
-        self.artist.clone()
+        self.artist.clone().unwrap_or_default()
     }
 
     fn album(&self) -> Option<&String> {
@@ -69,3 +658,328 @@ impl types::DisplayTrack for Track {
         "n/a"
     }
 }
+
+/// A track made of the given keys of an iTunes library, for tests.
+#[cfg(test)]
+pub(crate) fn test_track(entries: &[(&str, Value)]) -> Track {
+    let dict: Dictionary = entries
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.clone()))
+        .collect();
+    Track::try_from(dict).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{error_offset, parse_playlist, test_track, MediaKind, Playlist, Track};
+    use crate::error::ErrorKind;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn test_error_offset() {
+        assert_eq!(
+            error_offset("Serde(\"missing field `Artist`\") (offset 1024)"),
+            Some(1024)
+        );
+        assert_eq!(error_offset("UnexpectedEof"), None);
+    }
+
+    #[test]
+    fn test_track_metadata() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>Track ID</key><integer>1234</integer>
+    <key>Name</key><string>Bohemian Rhapsody</string>
+    <key>Artist</key><string>Queen</string>
+    <key>Album Artist</key><string>Queen</string>
+    <key>Album</key><string>A Night at the Opera</string>
+    <key>Total Time</key><integer>354320</integer>
+    <key>Track Number</key><integer>11</integer>
+    <key>Track Count</key><integer>12</integer>
+    <key>Rating</key><integer>100</integer>
+    <key>Loved</key><true/>
+    <key>Date Added</key><date>2019-10-01T12:30:00Z</date>
+    <key>Play Count</key><integer>42</integer>
+    <key>Play Date</key><integer>3653209385</integer>
+    <key>Play Date UTC</key><date>2019-10-05T08:23:05Z</date>
+    <key>Normalization</key><integer>1071</integer>
+</dict>
+</plist>"#;
+        let track: Track = plist::from_bytes(xml.as_bytes()).unwrap();
+        assert_eq!(track.track_id, Some(1234));
+        assert_eq!(track.album_artist.as_deref(), Some("Queen"));
+        assert_eq!(track.total_time, Some(354_320));
+        assert_eq!(track.track_number, Some(11));
+        assert_eq!(track.rating, Some(100));
+        assert_eq!(track.stars(), Some(5));
+        assert!(track.loved);
+        assert!(!track.compilation);
+        assert_eq!(
+            track.date_added.unwrap().to_rfc3339(),
+            "2019-10-01T12:30:00+00:00"
+        );
+        assert_eq!(track.play_count, Some(42));
+        assert_eq!(
+            track.play_date.unwrap().to_rfc3339(),
+            "2019-10-05T08:23:05+00:00"
+        );
+        assert!(track.extra.contains_key("Normalization"));
+        assert!(!track.extra.contains_key("Name"));
+    }
+
+    #[test]
+    fn test_track_stars() {
+        let rated =
+            |rating: i64| test_track(&[("Name", "Yellow".into()), ("Rating", rating.into())]);
+        assert_eq!(test_track(&[("Name", "Yellow".into())]).stars(), None);
+        assert_eq!(rated(0).stars(), None);
+        assert_eq!(rated(60).stars(), Some(3));
+        let computed = test_track(&[
+            ("Name", "Yellow".into()),
+            ("Rating", 80.into()),
+            ("Rating Computed", true.into()),
+        ]);
+        assert_eq!(computed.rating, Some(80));
+        assert_eq!(computed.stars(), None);
+    }
+
+    #[test]
+    fn test_playlist_keeps_valid_tracks() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>Tracks</key>
+    <dict>
+        <key>1</key>
+        <dict>
+            <key>Name</key><string>Voice Memo</string>
+        </dict>
+        <key>2</key>
+        <dict>
+            <key>Name</key><string>Yellow</string>
+            <key>Artist</key><string>Coldplay</string>
+            <key>Rating</key><string>five stars</string>
+        </dict>
+        <key>3</key>
+        <dict>
+            <key>Artist</key><string>Coldplay</string>
+        </dict>
+    </dict>
+</dict>
+</plist>"#;
+        let playlist: Playlist = plist::from_bytes(xml.as_bytes()).unwrap();
+        assert_eq!(playlist.major_version, None);
+        assert_eq!(playlist.tracks.len(), 1);
+        assert_eq!(playlist.tracks["1"].artist, None);
+        assert_eq!(playlist.warnings.len(), 2);
+        assert_eq!(
+            playlist.warnings[0].to_string(),
+            "track 2 (Yellow): field `Rating` should be an integer"
+        );
+        assert_eq!(
+            playlist.warnings[1].to_string(),
+            "track 3: missing field `Name`"
+        );
+    }
+
+    const LIBRARY_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>Major Version</key><integer>1</integer>
+    <key>Tracks</key>
+    <dict>
+        <key>1</key>
+        <dict>
+            <key>Name</key><string>Yellow</string>
+            <key>Artist</key><string>Coldplay</string>
+        </dict>
+    </dict>
+</dict>
+</plist>"#;
+
+    #[test]
+    fn test_parse_binary_plist() {
+        let value: plist::Value = plist::from_bytes(LIBRARY_XML.as_bytes()).unwrap();
+        let mut binary = Vec::new();
+        value.to_writer_binary(&mut binary).unwrap();
+
+        let playlist = parse_playlist("library.plist", &binary).unwrap();
+        assert_eq!(playlist.tracks["1"].name, "Yellow");
+    }
+
+    #[test]
+    fn test_parse_gzip_plist() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(LIBRARY_XML.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let playlist = parse_playlist("Library.xml.gz", &gzipped).unwrap();
+        assert_eq!(playlist.tracks["1"].name, "Yellow");
+    }
+
+    #[test]
+    fn test_parse_zip_plist() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("__MACOSX/._Library.xml", Default::default())
+            .unwrap();
+        writer.write_all(b"resource fork").unwrap();
+        writer
+            .start_file("Library.xml", Default::default())
+            .unwrap();
+        writer.write_all(LIBRARY_XML.as_bytes()).unwrap();
+        let zipped = writer.finish().unwrap().into_inner();
+
+        let playlist = parse_playlist("Library.zip", &zipped).unwrap();
+        assert_eq!(playlist.tracks["1"].name, "Yellow");
+    }
+
+    #[test]
+    fn test_parse_error_names_format() {
+        let truncated = &LIBRARY_XML[..LIBRARY_XML.len() - 20];
+        let error = parse_playlist("Library.xml", truncated.as_bytes()).unwrap_err();
+        match error.kind {
+            ErrorKind::PlistError { ref detail, .. } => {
+                assert!(
+                    detail.starts_with("could not parse XML plist"),
+                    "{}",
+                    detail
+                )
+            }
+            _ => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_library_playlists() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>Tracks</key>
+    <dict>
+        <key>10</key>
+        <dict>
+            <key>Name</key><string>Eye of the Tiger</string>
+        </dict>
+        <key>9</key>
+        <dict>
+            <key>Name</key><string>Stronger</string>
+        </dict>
+    </dict>
+    <key>Playlists</key>
+    <array>
+        <dict>
+            <key>Name</key><string>Library</string>
+            <key>Master</key><true/>
+            <key>Visible</key><false/>
+        </dict>
+        <dict>
+            <key>Name</key><string>Workout</string>
+            <key>Playlist Persistent ID</key><string>AAAA</string>
+            <key>Folder</key><true/>
+        </dict>
+        <dict>
+            <key>Name</key><string>Running</string>
+            <key>Playlist Persistent ID</key><string>BBBB</string>
+            <key>Parent Persistent ID</key><string>AAAA</string>
+            <key>Smart Info</key><data>AQEAAwAAAAIAAAAZAAAAAAAAAAcAAAABAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA</data>
+            <key>Playlist Items</key>
+            <array>
+                <dict><key>Track ID</key><integer>10</integer></dict>
+                <dict><key>Track ID</key><integer>9</integer></dict>
+            </array>
+        </dict>
+    </array>
+</dict>
+</plist>"#;
+        let library: Playlist = plist::from_bytes(xml.as_bytes()).unwrap();
+        assert_eq!(library.playlists.len(), 3);
+
+        let master = &library.playlists[0];
+        assert!(master.is_builtin());
+
+        let running = &library.playlists[2];
+        assert!(running.smart);
+        assert!(!running.is_builtin());
+        assert_eq!(library.folder_path(running), vec!["Workout"]);
+        assert_eq!(library.qualified_name(running, " / "), "Workout / Running");
+
+        let names: Vec<&str> = library
+            .playlist_tracks(running)
+            .iter()
+            .map(|track| track.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Eye of the Tiger", "Stronger"]);
+    }
+
+    #[test]
+    fn test_media_kind() {
+        let xml = |keys: &str| {
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>Name</key><string>Item</string>
+    {}
+</dict>
+</plist>"#,
+                keys
+            )
+        };
+        let kind = |keys: &str| {
+            plist::from_bytes::<Track>(xml(keys).as_bytes())
+                .unwrap()
+                .media_kind()
+        };
+
+        assert_eq!(
+            kind("<key>Kind</key><string>MPEG audio file</string>"),
+            MediaKind::Music
+        );
+        assert_eq!(
+            kind("<key>Kind</key><string>MPEG audio file</string><key>Podcast</key><true/>"),
+            MediaKind::Podcast
+        );
+        assert_eq!(
+            kind("<key>Kind</key><string>Protected book</string>"),
+            MediaKind::Audiobook
+        );
+        assert_eq!(
+            kind("<key>Kind</key><string>PDF document</string>"),
+            MediaKind::Document
+        );
+        assert_eq!(
+            kind("<key>Kind</key><string>MPEG-4 video file</string><key>Music Video</key><true/>"),
+            MediaKind::MusicVideo
+        );
+        assert_eq!(
+            kind("<key>Kind</key><string>MPEG-4 video file</string>"),
+            MediaKind::Movie
+        );
+        assert_eq!(
+            kind("<key>Genre</key><string>Voice Memo</string>"),
+            MediaKind::VoiceMemo
+        );
+        assert_eq!(
+            kind("<key>Track Type</key><string>URL</string>"),
+            MediaKind::Stream
+        );
+    }
+
+    #[test]
+    fn test_track_wrong_type() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>Name</key><string>Yellow</string>
+    <key>Artist</key><string>Coldplay</string>
+    <key>Rating</key><string>five stars</string>
+</dict>
+</plist>"#;
+        let result: Result<Track, _> = plist::from_bytes(xml.as_bytes());
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,255 @@
+//! Resolving track `Location` URLs to local files.
+//!
+//! iTunes stores locations as percent-encoded URLs such as
+//! `file://localhost/Users/me/Music/iTunes/Some%20Track.mp3`, or
+//! `file://localhost/C:/Users/me/Music/Some%20Track.mp3` on Windows. When a library has moved,
+//! e.g. from a Mac to a NAS, its locations still point at the old music folder, so a resolver can
+//! remap path prefixes before checking whether the files exist.
+use super::Track;
+use crate::error::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Decode a `file://` location URL into a path.
+///
+/// Returns `None` for locations that aren't local files, e.g. `http://` streams, or that don't
+/// decode to valid UTF-8.
+pub fn decode_location(location: &str) -> Option<PathBuf> {
+    let rest = strip_prefix_ignore_case(location, "file://")?;
+    // The host is empty or `localhost` for local files.
+    let rest = strip_prefix_ignore_case(rest, "localhost").unwrap_or(rest);
+    if !rest.starts_with('/') {
+        return None;
+    }
+    let decoded = percent_decode(rest)?;
+    Some(PathBuf::from(strip_drive_slash(&decoded)))
+}
+
+/// Encode a path as a `file://` URL, the inverse of `decode_location`.
+pub fn encode_location(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    let mut location = String::from("file://");
+    if !path.starts_with('/') {
+        // Windows paths like `C:/Users/...` still need a slash after the empty host.
+        location.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                location.push(byte as char)
+            }
+            _ => location.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    location
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    match s.get(..prefix.len()) {
+        Some(head) if head.eq_ignore_ascii_case(prefix) => Some(&s[prefix.len()..]),
+        _ => None,
+    }
+}
+
+/// Decode `%XX` escapes. Malformed escapes are kept as they are.
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let escape = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if let Some(byte) = escape {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Turn `/C:/Users/...` into `C:/Users/...`.
+fn strip_drive_slash(path: &str) -> &str {
+    let bytes = path.as_bytes();
+    let is_drive = bytes.len() >= 3 && bytes[1].is_ascii_alphabetic() && bytes[2] == b':';
+    if is_drive && (bytes.len() == 3 || bytes[3] == b'/') {
+        return &path[1..];
+    }
+    path
+}
+
+/// Replace the start of a path, e.g. the music folder of a library that has moved.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Remap {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl Remap {
+    /// Create a remap. `from` may be a path or a `file://` URL, like the library's `Music Folder`.
+    pub fn new<P: AsRef<Path>>(from: &str, to: P) -> Remap {
+        Remap {
+            from: decode_location(from).unwrap_or_else(|| PathBuf::from(from)),
+            to: to.as_ref().to_path_buf(),
+        }
+    }
+
+    fn apply(&self, path: &Path) -> Option<PathBuf> {
+        path.strip_prefix(&self.from)
+            .ok()
+            .map(|rest| self.to.join(rest))
+    }
+}
+
+impl FromStr for Remap {
+    type Err = Error;
+
+    /// Parse a remap given as `FROM=TO`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.find('=') {
+            Some(i) if i > 0 && i + 1 < s.len() => Ok(Remap::new(&s[..i], &s[i + 1..])),
+            _ => Err(Error::new(ErrorKind::Config(format!(
+                "invalid remap {}, expected FROM=TO",
+                s
+            )))),
+        }
+    }
+}
+
+/// A track's location as a local path, and whether the file is there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalFile {
+    pub path: PathBuf,
+    pub exists: bool,
+}
+
+/// Resolves location URLs to local paths, applying the first matching remap.
+#[derive(Clone, Debug, Default)]
+pub struct PathResolver {
+    remaps: Vec<Remap>,
+}
+
+impl PathResolver {
+    pub fn new(remaps: Vec<Remap>) -> PathResolver {
+        PathResolver { remaps }
+    }
+
+    /// The local path of a location URL, or `None` if it isn't a local file.
+    pub fn resolve(&self, location: &str) -> Option<PathBuf> {
+        let path = decode_location(location)?;
+        Some(
+            self.remaps
+                .iter()
+                .find_map(|remap| remap.apply(&path))
+                .unwrap_or(path),
+        )
+    }
+
+    /// The local file of a track, or `None` if the track has no local location.
+    pub fn local_file(&self, track: &Track) -> Option<LocalFile> {
+        let path = self.resolve(track.location.as_ref()?)?;
+        let exists = path.is_file();
+        Some(LocalFile { path, exists })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_location, encode_location, LocalFile, PathResolver, Remap};
+    use crate::itunes::test_track;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_decode_location() {
+        assert_eq!(
+            decode_location(
+                "file://localhost/Users/me/Music/Sigur%20R%C3%B3s/01%20Glos%C3%B3li.m4a"
+            ),
+            Some(PathBuf::from("/Users/me/Music/Sigur Rós/01 Glosóli.m4a"))
+        );
+        assert_eq!(
+            decode_location("file:///home/me/Music/100%25.mp3"),
+            Some(PathBuf::from("/home/me/Music/100%.mp3"))
+        );
+        assert_eq!(
+            decode_location("file://localhost/C:/Users/me/Music/Track.mp3"),
+            Some(PathBuf::from("C:/Users/me/Music/Track.mp3"))
+        );
+        assert_eq!(decode_location("http://radio.example.com/stream"), None);
+        assert_eq!(decode_location("file://localhost/bad%FF.mp3"), None);
+    }
+
+    #[test]
+    fn test_encode_location() {
+        assert_eq!(
+            encode_location(Path::new("/Users/me/Music/Sigur Rós/100%.m4a")),
+            "file:///Users/me/Music/Sigur%20R%C3%B3s/100%25.m4a"
+        );
+        assert_eq!(
+            encode_location(Path::new("C:/Music/Track.mp3")),
+            "file:///C:/Music/Track.mp3"
+        );
+        let path = Path::new("/mnt/nas/AC/DC #1 [live].flac");
+        assert_eq!(
+            decode_location(&encode_location(path)).as_deref(),
+            Some(path)
+        );
+    }
+
+    #[test]
+    fn test_remap_prefix() {
+        let remap: Remap = "file://localhost/Users/me/Music/iTunes/=/mnt/nas/music"
+            .parse()
+            .unwrap();
+        assert_eq!(remap.from, PathBuf::from("/Users/me/Music/iTunes/"));
+        assert!("/Users/me".parse::<Remap>().is_err());
+
+        let resolver = PathResolver::new(vec![remap]);
+        assert_eq!(
+            resolver.resolve("file://localhost/Users/me/Music/iTunes/Artist/Track.mp3"),
+            Some(PathBuf::from("/mnt/nas/music/Artist/Track.mp3"))
+        );
+        // Prefixes only match whole path components.
+        assert_eq!(
+            resolver.resolve("file://localhost/Users/me/Music/iTunes%20Old/Track.mp3"),
+            Some(PathBuf::from("/Users/me/Music/iTunes Old/Track.mp3"))
+        );
+    }
+
+    #[test]
+    fn test_local_file_exists() {
+        let dir = std::env::temp_dir().join(format!("ispot-location-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Found Track.mp3"), b"").unwrap();
+
+        let resolver = PathResolver::new(vec![Remap::new("/Music", &dir)]);
+        let track =
+            |location: &str| test_track(&[("Name", "Track".into()), ("Location", location.into())]);
+        assert_eq!(
+            resolver.local_file(&track("file:///Music/Found%20Track.mp3")),
+            Some(LocalFile {
+                path: dir.join("Found Track.mp3"),
+                exists: true,
+            })
+        );
+        assert_eq!(
+            resolver.local_file(&track("file:///Music/Missing.mp3")),
+            Some(LocalFile {
+                path: dir.join("Missing.mp3"),
+                exists: false,
+            })
+        );
+        assert_eq!(
+            resolver.local_file(&test_track(&[("Name", "Track".into())])),
+            None
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,563 @@
+//! Event based loading of large iTunes libraries.
+//!
+//! `load_playlist` deserializes a whole library at once, which for libraries with hundreds of
+//! thousands of tracks means holding every track in memory. The loaders in this module walk the
+//! plist events instead: a first pass reads only the `Playlists` array, and a second pass builds
+//! just the tracks referenced by the selected playlists.
+//!
+//! Only uncompressed and gzip-compressed XML plists can be streamed. Binary plists, zip archives
+//! and stdin fall back to `load_playlist`.
+use super::{Compression, LibraryPlaylist, Playlist, PlistFormat};
+use crate::error::{Error, ErrorKind};
+use base64::Engine;
+use flate2::read::GzDecoder;
+use plist::{Date, Dictionary, Integer, Value};
+use quick_xml::escape::resolve_xml_entity;
+use quick_xml::events::Event as XmlEvent;
+use quick_xml::Reader;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::rc::Rc;
+
+/// Report progress at most once per this many bytes read.
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+/// What the loader is currently doing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    Playlists,
+    Tracks,
+}
+
+/// Progress of a streaming load, passed to the progress callback.
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    pub stage: Stage,
+    /// Bytes read from the file so far, before decompression.
+    pub bytes_read: u64,
+    pub total_bytes: u64,
+}
+
+/// Load the playlists of a library without any tracks.
+pub fn load_playlists<P>(path: &str, mut progress: P) -> Result<Playlist, Error>
+where
+    P: FnMut(Progress),
+{
+    if !is_streamable(path)? {
+        let mut library = super::load_playlist(path)?;
+        library.tracks.clear();
+        return Ok(library);
+    }
+
+    read_playlists(path, &mut progress)
+}
+
+/// Load the playlists chosen by `select`, along with only the tracks they reference.
+///
+/// `select` is called for every playlist that isn't a folder. Folders are always kept so folder
+/// paths can still be resolved.
+pub fn load_library<S, P>(path: &str, select: S, mut progress: P) -> Result<Playlist, Error>
+where
+    S: Fn(&Playlist, &LibraryPlaylist) -> bool,
+    P: FnMut(Progress),
+{
+    if !is_streamable(path)? {
+        let mut library = super::load_playlist(path)?;
+        retain_selected(&mut library, select);
+        let wanted: HashSet<String> = wanted_track_keys(&library);
+        library.tracks.retain(|key, _| wanted.contains(key));
+        return Ok(library);
+    }
+
+    let mut library = read_playlists(path, &mut progress)?;
+    retain_selected(&mut library, select);
+    let wanted = wanted_track_keys(&library);
+    read_tracks(path, &mut library, &wanted, &mut progress)?;
+    Ok(library)
+}
+
+fn retain_selected<S>(library: &mut Playlist, select: S)
+where
+    S: Fn(&Playlist, &LibraryPlaylist) -> bool,
+{
+    let selected: Vec<bool> = library
+        .playlists
+        .iter()
+        .map(|playlist| playlist.folder || select(library, playlist))
+        .collect();
+    let mut selected = selected.into_iter();
+    library
+        .playlists
+        .retain(|_| selected.next().unwrap_or(false));
+}
+
+fn wanted_track_keys(library: &Playlist) -> HashSet<String> {
+    library
+        .playlists
+        .iter()
+        .flat_map(|playlist| playlist.track_ids.iter())
+        .map(|id| id.to_string())
+        .collect()
+}
+
+/// Whether the file is an XML plist, possibly gzip-compressed, that can be read as a stream.
+fn is_streamable(path: &str) -> Result<bool, Error> {
+    if path == super::STDIN_PATH {
+        return Ok(false);
+    }
+    let (compression, mut reader) = match open(path) {
+        Ok(opened) => opened,
+        // Let `load_playlist` report missing files and directories.
+        Err(_) => return Ok(false),
+    };
+    if compression == Compression::Zip {
+        return Ok(false);
+    }
+    let mut head = Vec::new();
+    reader
+        .by_ref()
+        .take(512)
+        .read_to_end(&mut head)
+        .map_err(|e| io_error(path, e))?;
+    Ok(PlistFormat::detect(&head) == PlistFormat::Xml)
+}
+
+/// Open a file, transparently decompressing gzip. Zip archives are reported but not unpacked.
+fn open(path: &str) -> Result<(Compression, Box<dyn BufRead>), Error> {
+    open_counted(path, Rc::new(Cell::new(0)))
+}
+
+fn open_counted(
+    path: &str,
+    counter: Rc<Cell<u64>>,
+) -> Result<(Compression, Box<dyn BufRead>), Error> {
+    let file = File::open(path).map_err(|e| io_error(path, e))?;
+    let mut reader = BufReader::new(CountingReader {
+        inner: file,
+        count: counter,
+    });
+    let magic = reader.fill_buf().map_err(|e| io_error(path, e))?;
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        let decoder = GzDecoder::new(reader);
+        return Ok((Compression::Gzip, Box::new(BufReader::new(decoder))));
+    }
+    if magic.starts_with(b"PK\x03\x04") {
+        return Ok((Compression::Zip, Box::new(reader)));
+    }
+    Ok((Compression::None, Box::new(reader)))
+}
+
+fn io_error(path: &str, error: io::Error) -> Error {
+    Error::with_source(ErrorKind::Io(path.to_string()), error)
+}
+
+/// Counts the bytes read from the underlying file so progress can be reported.
+struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.set(self.count.get() + read as u64);
+        Ok(read)
+    }
+}
+
+/// A structural event of an XML plist.
+#[derive(Debug)]
+enum Event {
+    StartArray,
+    StartDictionary,
+    EndCollection,
+    /// A dictionary key or a scalar value.
+    Value(Value),
+}
+
+/// A stream of plist events from an XML file, with progress reporting.
+///
+/// `plist::from_reader_xml` keeps the whole document in memory, so the XML is parsed here with a
+/// reader that only ever holds the element currently being read.
+struct Events<'a, P: FnMut(Progress)> {
+    path: &'a str,
+    reader: Reader<Box<dyn BufRead>>,
+    buffer: Vec<u8>,
+    stage: Stage,
+    bytes_read: Rc<Cell<u64>>,
+    total_bytes: u64,
+    next_report: u64,
+    progress: &'a mut P,
+}
+
+impl<'a, P: FnMut(Progress)> Events<'a, P> {
+    fn open(path: &'a str, stage: Stage, progress: &'a mut P) -> Result<Self, Error> {
+        let bytes_read = Rc::new(Cell::new(0));
+        let (_, input) = open_counted(path, bytes_read.clone())?;
+        let total_bytes = std::fs::metadata(path)
+            .map_err(|e| io_error(path, e))?
+            .len();
+        let mut reader = Reader::from_reader(input);
+        let config = reader.config_mut();
+        config.trim_text(false);
+        config.check_end_names = true;
+        config.expand_empty_elements = true;
+        Ok(Events {
+            path,
+            reader,
+            buffer: Vec::new(),
+            stage,
+            bytes_read,
+            total_bytes,
+            next_report: 0,
+            progress,
+        })
+    }
+
+    /// The next event, or an error if the plist ends early or is malformed.
+    fn next(&mut self) -> Result<Event, Error> {
+        let bytes_read = self.bytes_read.get();
+        if bytes_read >= self.next_report {
+            (self.progress)(Progress {
+                stage: self.stage,
+                bytes_read,
+                total_bytes: self.total_bytes,
+            });
+            self.next_report = bytes_read + PROGRESS_INTERVAL;
+        }
+
+        loop {
+            self.buffer.clear();
+            let element = match self.reader.read_event_into(&mut self.buffer) {
+                Ok(XmlEvent::Start(tag)) => tag.local_name().as_ref().to_string(),
+                Ok(XmlEvent::End(tag)) => match tag.local_name().as_ref() {
+                    "array" | "dict" => return Ok(Event::EndCollection),
+                    _ => continue,
+                },
+                Ok(XmlEvent::Eof) => return Err(self.malformed("unexpected end of file")),
+                Ok(_) => continue,
+                Err(e) => return Err(self.malformed(&e.to_string())),
+            };
+
+            let value = match element.as_str() {
+                "plist" => continue,
+                "array" => return Ok(Event::StartArray),
+                "dict" => return Ok(Event::StartDictionary),
+                "key" | "string" => Value::String(self.read_text()?),
+                "true" | "false" => {
+                    self.read_text()?;
+                    Value::Boolean(element == "true")
+                }
+                "integer" => {
+                    let text = self.read_text()?;
+                    let integer = match text.trim().parse::<i64>() {
+                        Ok(i) => Integer::from(i),
+                        Err(_) => text
+                            .trim()
+                            .parse::<u64>()
+                            .map(Integer::from)
+                            .map_err(|_| self.malformed(&format!("invalid integer {}", text)))?,
+                    };
+                    Value::Integer(integer)
+                }
+                "real" => {
+                    let text = self.read_text()?;
+                    Value::Real(
+                        text.trim()
+                            .parse()
+                            .map_err(|_| self.malformed(&format!("invalid real {}", text)))?,
+                    )
+                }
+                "date" => {
+                    let text = self.read_text()?;
+                    Value::Date(
+                        Date::from_xml_format(text.trim())
+                            .map_err(|_| self.malformed(&format!("invalid date {}", text)))?,
+                    )
+                }
+                "data" => {
+                    let mut text = self.read_text()?;
+                    text.retain(|c| !c.is_ascii_whitespace());
+                    Value::Data(
+                        base64::engine::general_purpose::STANDARD
+                            .decode(&text)
+                            .map_err(|_| self.malformed("invalid base64 data"))?,
+                    )
+                }
+                _ => return Err(self.malformed(&format!("unknown element <{}>", element))),
+            };
+            return Ok(Event::Value(value));
+        }
+    }
+
+    /// Read the text of the current element up to its closing tag.
+    fn read_text(&mut self) -> Result<String, Error> {
+        let mut content = String::new();
+        loop {
+            self.buffer.clear();
+            match self.reader.read_event_into(&mut self.buffer) {
+                Ok(XmlEvent::Text(text)) => content.push_str(&text.xml10_content()),
+                Ok(XmlEvent::CData(data)) => content.push_str(&data),
+                Ok(XmlEvent::GeneralRef(reference)) => match reference.resolve_char_ref() {
+                    Ok(Some(c)) => content.push(c),
+                    Ok(None) => {
+                        if let Some(entity) = resolve_xml_entity(&reference) {
+                            content.push_str(entity);
+                        }
+                    }
+                    Err(e) => return Err(self.malformed(&e.to_string())),
+                },
+                Ok(XmlEvent::End(_)) => return Ok(content),
+                Ok(XmlEvent::Start(_)) => return Err(self.malformed("unexpected element")),
+                Ok(XmlEvent::Eof) => return Err(self.malformed("unexpected end of file")),
+                Ok(_) => (),
+                Err(e) => return Err(self.malformed(&e.to_string())),
+            }
+        }
+    }
+
+    fn malformed(&self, detail: &str) -> Error {
+        Error::new(ErrorKind::PlistError {
+            path: self.path.to_string(),
+            detail: format!(
+                "could not parse XML plist: {} (offset {})",
+                detail,
+                self.reader.buffer_position()
+            ),
+        })
+    }
+
+    /// Expect the start of the top level dictionary.
+    fn start_root(&mut self) -> Result<(), Error> {
+        match self.next()? {
+            Event::StartDictionary => Ok(()),
+            _ => Err(self.malformed("expected a dictionary at the top level")),
+        }
+    }
+
+    /// The next key of the dictionary being read, or `None` at its end.
+    fn next_key(&mut self) -> Result<Option<String>, Error> {
+        match self.next()? {
+            Event::Value(Value::String(key)) => Ok(Some(key)),
+            Event::EndCollection => Ok(None),
+            _ => Err(self.malformed("expected a dictionary key")),
+        }
+    }
+
+    /// Build the next value in full.
+    fn read_value(&mut self) -> Result<Value, Error> {
+        let event = self.next()?;
+        self.read_value_from(event)
+    }
+
+    fn read_value_from(&mut self, event: Event) -> Result<Value, Error> {
+        Ok(match event {
+            Event::StartArray => {
+                let mut array = Vec::new();
+                loop {
+                    match self.next()? {
+                        Event::EndCollection => break,
+                        event => array.push(self.read_value_from(event)?),
+                    }
+                }
+                Value::Array(array)
+            }
+            Event::StartDictionary => {
+                let mut dict = Dictionary::new();
+                while let Some(key) = self.next_key()? {
+                    let value = self.read_value()?;
+                    dict.insert(key, value);
+                }
+                Value::Dictionary(dict)
+            }
+            Event::EndCollection => return Err(self.malformed("unexpected end of collection")),
+            Event::Value(value) => value,
+        })
+    }
+
+    /// Skip over the next value without building it.
+    fn skip_value(&mut self) -> Result<(), Error> {
+        let mut depth = 0;
+        loop {
+            match self.next()? {
+                Event::StartArray | Event::StartDictionary => depth += 1,
+                Event::EndCollection => depth -= 1,
+                Event::Value(_) => (),
+            }
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// First pass: read everything except the tracks.
+fn read_playlists<P: FnMut(Progress)>(path: &str, progress: &mut P) -> Result<Playlist, Error> {
+    let mut library = Playlist {
+        major_version: None,
+        music_folder: None,
+        tracks: BTreeMap::new(),
+        playlists: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    let mut events = Events::open(path, Stage::Playlists, progress)?;
+    events.start_root()?;
+    while let Some(key) = events.next_key()? {
+        match key.as_str() {
+            "Major Version" => {
+                library.major_version = match events.read_value()? {
+                    Value::Integer(i) => i.as_signed().map(|i| i as i32),
+                    _ => None,
+                }
+            }
+            "Music Folder" => {
+                library.music_folder = match events.read_value()? {
+                    Value::String(s) => Some(s),
+                    _ => None,
+                }
+            }
+            "Playlists" => match events.next()? {
+                Event::StartArray => loop {
+                    match events.next()? {
+                        Event::EndCollection => break,
+                        event => {
+                            let value = events.read_value_from(event)?;
+                            library.insert_playlist(value);
+                        }
+                    }
+                },
+                _ => return Err(events.malformed("field `Playlists` should be an array")),
+            },
+            _ => events.skip_value()?,
+        }
+    }
+    Ok(library)
+}
+
+/// Second pass: build the tracks whose keys are in `wanted`, stopping once `Tracks` ends.
+fn read_tracks<P: FnMut(Progress)>(
+    path: &str,
+    library: &mut Playlist,
+    wanted: &HashSet<String>,
+    progress: &mut P,
+) -> Result<(), Error> {
+    let mut events = Events::open(path, Stage::Tracks, progress)?;
+    events.start_root()?;
+    while let Some(key) = events.next_key()? {
+        if key != "Tracks" {
+            events.skip_value()?;
+            continue;
+        }
+        match events.next()? {
+            Event::StartDictionary => (),
+            _ => return Err(events.malformed("field `Tracks` should be a dictionary")),
+        }
+        while let Some(track_key) = events.next_key()? {
+            if wanted.contains(&track_key) {
+                let value = events.read_value()?;
+                library.insert_track(track_key, value);
+            } else {
+                events.skip_value()?;
+            }
+        }
+        break;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_library, load_playlists, Stage};
+    use std::fs;
+    use std::io::Write;
+
+    const LIBRARY_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>Major Version</key><integer>1</integer>
+    <key>Tracks</key>
+    <dict>
+        <key>1</key>
+        <dict>
+            <key>Name</key><string>Yellow</string>
+            <key>Artist</key><string>Coldplay</string>
+        </dict>
+        <key>2</key>
+        <dict>
+            <key>Name</key><string>Clocks</string>
+            <key>Artist</key><string>Coldplay</string>
+        </dict>
+        <key>3</key>
+        <dict>
+            <key>Name</key><string>Stronger</string>
+            <key>Artist</key><string>Kanye West</string>
+        </dict>
+    </dict>
+    <key>Playlists</key>
+    <array>
+        <dict>
+            <key>Name</key><string>Coldplay</string>
+            <key>Playlist Items</key>
+            <array>
+                <dict><key>Track ID</key><integer>2</integer></dict>
+                <dict><key>Track ID</key><integer>1</integer></dict>
+            </array>
+        </dict>
+        <dict>
+            <key>Name</key><string>Workout</string>
+            <key>Playlist Items</key>
+            <array>
+                <dict><key>Track ID</key><integer>3</integer></dict>
+            </array>
+        </dict>
+    </array>
+</dict>
+</plist>"#;
+
+    fn write_library(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("ispot-{}-{}.xml", name, std::process::id()));
+        fs::File::create(&path)
+            .unwrap()
+            .write_all(LIBRARY_XML.as_bytes())
+            .unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_load_library_only_selected_tracks() {
+        let path = write_library("stream-selected");
+        let mut stages = Vec::new();
+        let library = load_library(
+            &path,
+            |_, playlist| playlist.name == "Coldplay",
+            |progress| stages.push(progress.stage),
+        )
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(library.major_version, Some(1));
+        assert_eq!(library.playlists.len(), 1);
+        assert_eq!(library.tracks.len(), 2);
+        let names: Vec<&str> = library
+            .playlist_tracks(&library.playlists[0])
+            .iter()
+            .map(|track| track.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Clocks", "Yellow"]);
+        assert!(stages.contains(&Stage::Playlists));
+        assert!(stages.contains(&Stage::Tracks));
+    }
+
+    #[test]
+    fn test_load_playlists_without_tracks() {
+        let path = write_library("stream-playlists");
+        let library = load_playlists(&path, |_| ()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(library.playlists.len(), 2);
+        assert!(library.tracks.is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+//! A journal of a conversion in progress, so an interrupted run can be resumed without searching
+//! the matched tracks again or creating their playlists twice.
+//!
+//! The journal is a text file with one tab separated record per line, appended and flushed as the
+//! conversion goes:
+//!
+//! ```text
+//! track    <track key>    <spotify:track: URI, empty if not found>
+//! created  <playlist key> <Spotify playlist ID>
+//! done     <playlist key>
+//! ```
+//!
+//! A playlist key identifies a source playlist across runs, e.g. by its iTunes persistent ID,
+//! since names can repeat or change.
+use crate::error::{Error, ErrorKind};
+use crate::source::SourceTrack;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+
+/// How far the Spotify playlist of a source playlist got.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlaylistProgress {
+    /// The Spotify playlist, once it was created. The tracks added to it so far are read back
+    /// from Spotify, as a run can end between adding a track and journaling it.
+    pub id: Option<String>,
+    /// Whether the playlist is complete.
+    pub done: bool,
+}
+
+/// The journal of a conversion. The default journal records nothing, for commands run without
+/// one.
+#[derive(Debug, Default)]
+pub struct Journal {
+    path: String,
+    file: Option<File>,
+    tracks: HashMap<String, Option<String>>,
+    playlists: HashMap<String, PlaylistProgress>,
+}
+
+impl Journal {
+    /// Open the journal at `path`. When resuming, the records of the interrupted run are read and
+    /// added to, otherwise the journal starts empty.
+    pub fn open(path: &str, resume: bool) -> Result<Journal, Error> {
+        let io_error = |e: io::Error| Error::with_source(ErrorKind::Io(path.to_string()), e);
+        let mut journal = Journal {
+            path: path.to_string(),
+            ..Default::default()
+        };
+        let mut content = String::new();
+        if resume {
+            match fs::read_to_string(path) {
+                Ok(existing) => content = existing,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                    info!("no journal at {}, starting from the beginning", path)
+                }
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+        // A line without its newline was cut off when the run was interrupted.
+        for line in content.split_inclusive('\n') {
+            match line.strip_suffix('\n') {
+                Some(record) => journal.apply(record),
+                None => warn!("ignoring incomplete journal record {}", line),
+            }
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resume)
+            .truncate(!resume)
+            .open(path)
+            .map_err(io_error)?;
+        if !content.is_empty() && !content.ends_with('\n') {
+            file.write_all(b"\n").map_err(io_error)?;
+        }
+        journal.file = Some(file);
+        Ok(journal)
+    }
+
+    /// The journaled match of a track: `None` if it wasn't searched yet, `Some(None)` if it
+    /// wasn't found.
+    pub fn track(&self, track: &SourceTrack) -> Option<Option<&str>> {
+        self.tracks.get(&track_key(track)).map(|uri| uri.as_deref())
+    }
+
+    /// Record the match of a track, or that it wasn't found.
+    pub fn record_track(&mut self, track: &SourceTrack, uri: Option<&str>) -> Result<(), Error> {
+        let key = track_key(track);
+        self.append(&["track", &key, uri.unwrap_or_default()])
+    }
+
+    /// Check that no two of the source playlists of a run share a key, which would mix up their
+    /// records.
+    pub fn check_keys<'a, I>(&self, keys: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        if self.file.is_none() {
+            return Ok(());
+        }
+        let mut seen = HashSet::new();
+        for key in keys {
+            if !seen.insert(sanitize(key)) {
+                return Err(Error::new(ErrorKind::Config(format!(
+                    "more than one playlist is journaled as {}",
+                    key
+                ))));
+            }
+        }
+        Ok(())
+    }
+
+    /// How far the Spotify playlist of the source playlist `key` got.
+    pub fn playlist(&self, key: &str) -> PlaylistProgress {
+        self.playlists
+            .get(&sanitize(key))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Record that the Spotify playlist `id` was created for the source playlist `key`.
+    pub fn record_created(&mut self, key: &str, id: &str) -> Result<(), Error> {
+        self.append(&["created", &sanitize(key), id])
+    }
+
+    /// Record that the Spotify playlist of `key` is complete.
+    pub fn record_done(&mut self, key: &str) -> Result<(), Error> {
+        self.append(&["done", &sanitize(key)])
+    }
+
+    /// Write a record and apply it. Records are written one at a time, so the journal is up to
+    /// date however the run ends.
+    fn append(&mut self, fields: &[&str]) -> Result<(), Error> {
+        let file = match self.file {
+            Some(ref mut file) => file,
+            None => return Ok(()),
+        };
+        let path = &self.path;
+        let record = fields.join("\t");
+        file.write_all(format!("{}\n", record).as_bytes())
+            .and_then(|_| file.flush())
+            .map_err(|e| Error::with_source(ErrorKind::Io(path.clone()), e))?;
+        self.apply(&record);
+        Ok(())
+    }
+
+    fn apply(&mut self, record: &str) {
+        let fields: Vec<&str> = record.split('\t').collect();
+        match fields[..] {
+            ["track", key, uri] => {
+                let uri = Some(uri.to_string()).filter(|uri| !uri.is_empty());
+                self.tracks.insert(key.to_string(), uri);
+            }
+            ["created", key, id] => {
+                self.playlists.entry(key.to_string()).or_default().id = Some(id.to_string());
+            }
+            ["done", key] => self.playlists.entry(key.to_string()).or_default().done = true,
+            _ => warn!("ignoring invalid journal record {}", record),
+        }
+    }
+}
+
+/// What identifies a track across runs: everything it is matched by.
+fn track_key(track: &SourceTrack) -> String {
+    let number = |n: Option<u32>| n.map(|n| n.to_string()).unwrap_or_default();
+    let fields = [
+        track.spotify_uri.clone().unwrap_or_default(),
+        track.isrc.clone().unwrap_or_default(),
+        track.name.clone(),
+        track.artist.clone().unwrap_or_default(),
+        track.album_artist.clone().unwrap_or_default(),
+        track.album.clone().unwrap_or_default(),
+        number(track.disc_number),
+        number(track.track_number),
+        track.duration.map(|d| d.to_string()).unwrap_or_default(),
+    ];
+    sanitize(&fields.join("\u{1f}"))
+}
+
+/// Keep tabs and newlines out of a journal field.
+fn sanitize(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Journal, PlaylistProgress};
+    use crate::source::SourceTrack;
+    use std::fs;
+
+    #[test]
+    fn test_journal() {
+        let path = std::env::temp_dir().join(format!("ispot-journal-{}.tsv", std::process::id()));
+        let path = path.to_str().unwrap();
+        let found = SourceTrack {
+            name: "Running\tUp That Hill".to_string(),
+            artist: Some("Kate Bush".to_string()),
+            ..Default::default()
+        };
+        let missing = SourceTrack {
+            name: "Demo".to_string(),
+            ..Default::default()
+        };
+
+        let mut journal = Journal::open(path, false).unwrap();
+        assert_eq!(journal.track(&found), None);
+        journal
+            .record_track(&found, Some("spotify:track:1"))
+            .unwrap();
+        journal.record_track(&missing, None).unwrap();
+        journal.record_created("4F3A1C2B", "abc").unwrap();
+        assert!(journal.check_keys(vec!["4F3A1C2B", "songs.m3u#1"]).is_ok());
+        assert!(journal
+            .check_keys(vec!["songs.m3u#1", "songs.m3u#1"])
+            .is_err());
+        drop(journal);
+        // The run was interrupted while writing a record.
+        let mut content = fs::read_to_string(path).unwrap();
+        content.push_str("done\t4F3A1C2B");
+        fs::write(path, content).unwrap();
+
+        let mut journal = Journal::open(path, true).unwrap();
+        assert_eq!(journal.track(&found), Some(Some("spotify:track:1")));
+        assert_eq!(journal.track(&missing), Some(None));
+        assert_eq!(
+            journal.playlist("4F3A1C2B"),
+            PlaylistProgress {
+                id: Some("abc".to_string()),
+                done: false,
+            }
+        );
+        journal.record_done("4F3A1C2B").unwrap();
+        drop(journal);
+        assert!(Journal::open(path, true).unwrap().playlist("4F3A1C2B").done);
+
+        // Without resuming, the journal starts over.
+        let journal = Journal::open(path, false).unwrap();
+        assert_eq!(journal.track(&found), None);
+        fs::remove_file(path).unwrap();
+
+        let mut disabled = Journal::default();
+        disabled.record_track(&found, None).unwrap();
+        assert_eq!(disabled.track(&found), None);
+        assert!(disabled.check_keys(vec!["", ""]).is_ok());
+    }
+}
@@ -1,8 +1,19 @@
+mod artists;
 pub mod command;
-mod error;
-mod itunes;
+pub mod cover;
+pub mod dedupe;
+pub mod error;
+pub mod export;
+pub mod generate;
+pub mod itunes;
+pub mod journal;
+mod limiter;
 pub mod logging;
+pub mod matching;
 mod output;
+pub mod playlist;
+mod progress;
+pub mod source;
 mod spotify;
 mod types;
 mod util;
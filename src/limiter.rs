@@ -0,0 +1,135 @@
+//! A token bucket shared by every thread calling the Spotify API, so concurrent searches stay
+//! under one request rate and all of them wait out a `Retry-After` together.
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    /// Until when the API asked for no more requests.
+    paused_until: Option<Instant>,
+}
+
+/// Requests are let through at `rate` per second on average, with bursts of up to `capacity`.
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub fn new(rate: f64, capacity: f64) -> TokenBucket {
+        TokenBucket {
+            rate,
+            capacity,
+            bucket: Mutex::new(Bucket {
+                tokens: capacity,
+                refilled: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Wait until a request may be made.
+    pub fn acquire(&self) {
+        while let Err(wait) = self.try_acquire(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+
+    /// Stop every request for `duration`, as the `Retry-After` of a rate limited response asks.
+    /// The bucket is empty afterwards, so requests pick up again at the average rate.
+    pub fn pause(&self, duration: Duration) {
+        self.pause_at(Instant::now(), duration);
+    }
+
+    /// Take a token at `now`, or tell how long to wait before trying again.
+    fn try_acquire(&self, now: Instant) -> Result<(), Duration> {
+        let mut bucket = self.bucket();
+        if let Some(paused_until) = bucket.paused_until {
+            if paused_until > now {
+                return Err(paused_until - now);
+            }
+            bucket.paused_until = None;
+            bucket.refilled = paused_until;
+        }
+        let elapsed = now.saturating_duration_since(bucket.refilled);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * self.rate).min(self.capacity);
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+
+    fn pause_at(&self, now: Instant, duration: Duration) {
+        let mut bucket = self.bucket();
+        let until = now + duration;
+        bucket.paused_until = Some(
+            bucket
+                .paused_until
+                .map_or(until, |paused| paused.max(until)),
+        );
+        bucket.tokens = 0.0;
+    }
+
+    /// The bucket. A panic while it was locked leaves it usable, as it holds no invariant.
+    fn bucket(&self) -> MutexGuard<'_, Bucket> {
+        self.bucket.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TokenBucket;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = TokenBucket::new(2.0, 3.0);
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.try_acquire(start), Ok(()));
+        }
+        assert_eq!(limiter.try_acquire(start), Err(Duration::from_millis(500)));
+        assert_eq!(
+            limiter.try_acquire(start + Duration::from_millis(500)),
+            Ok(())
+        );
+
+        // A long wait refills no more than the capacity.
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(limiter.try_acquire(later), Ok(()));
+        }
+        assert!(limiter.try_acquire(later).is_err());
+    }
+
+    #[test]
+    fn test_pause() {
+        let limiter = TokenBucket::new(10.0, 10.0);
+        let start = Instant::now();
+        limiter.pause_at(start, Duration::from_secs(30));
+        // A shorter Retry-After doesn't cut the pause short.
+        limiter.pause_at(start + Duration::from_secs(1), Duration::from_secs(5));
+        assert_eq!(
+            limiter.try_acquire(start + Duration::from_secs(10)),
+            Err(Duration::from_secs(20))
+        );
+        let resumed = start + Duration::from_secs(30);
+        assert_eq!(
+            limiter.try_acquire(resumed),
+            Err(Duration::from_millis(100))
+        );
+        assert_eq!(
+            limiter.try_acquire(resumed + Duration::from_millis(100)),
+            Ok(())
+        );
+    }
+}
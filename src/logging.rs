@@ -1,5 +1,25 @@
+use crate::progress;
 use env_logger;
-use log::LevelFilter;
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Writes log records around the progress bar, so they don't end up in the middle of it.
+struct Logger(env_logger::Logger);
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.0.matches(record) {
+            progress::suspend(|| self.0.log(record));
+        }
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+    }
+}
 
 pub fn configure(verbosity: u64) {
     let mut builder = env_logger::Builder::new();
@@ -11,8 +31,10 @@ pub fn configure(verbosity: u64) {
     match verbosity {
         0 => builder.filter_level(LevelFilter::Info),
         1 => builder.filter_level(LevelFilter::Debug),
-        2 | _ => builder.filter_level(LevelFilter::Trace),
+        _ => builder.filter_level(LevelFilter::Trace),
     };
 
-    builder.init();
+    let logger = builder.build();
+    log::set_max_level(logger.filter());
+    log::set_boxed_logger(Box::new(Logger(logger))).expect("logger configured twice");
 }
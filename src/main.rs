@@ -1,6 +1,15 @@
-use clap::{App, AppSettings, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgGroup, SubCommand};
 use ispot::command;
+use ispot::dedupe;
+use ispot::export;
+use ispot::generate;
+use ispot::itunes;
 use ispot::logging;
+use ispot::matching;
+use ispot::playlist;
+use ispot::source;
+use std::error::Error;
+use std::process;
 #[macro_use]
 extern crate log;
 
@@ -23,10 +32,98 @@ fn main() {
                         .about("Display an iTunes playlist")
                         .arg(
                             Arg::with_name("playlist")
-                                .help("Path to iTunes playlist file")
+                                .help("Path to iTunes playlist file, or - to read from stdin")
                                 .index(1)
                                 .required(true),
                         ),
+                )
+                .subcommand(
+                    SubCommand::with_name("check-files")
+                        .about("Check which tracks of an iTunes playlist or library exist as local files")
+                        .arg(
+                            Arg::with_name("playlist")
+                                .help("Path to iTunes playlist file or Library.xml, or - to read from stdin")
+                                .index(1)
+                                .required(true),
+                        )
+                        .arg(Arg::with_name("remap")
+                             .help("Replace the start of track locations, e.g. /Users/me/Music=/mnt/nas/music. Can be repeated")
+                             .long("remap")
+                             .value_name("FROM=TO")
+                             .takes_value(true)
+                             .multiple(true)
+                             .number_of_values(1)
+                        )
+                        .arg(Arg::with_name("music-folder")
+                             .help("Where the library's Music Folder is now, if it has moved")
+                             .long("music-folder")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("missing-only")
+                             .help("Only list tracks whose files are missing")
+                             .long("missing-only")
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("export")
+                        .about("Export an iTunes playlist to M3U8, XSPF or CSV")
+                        .arg(
+                            Arg::with_name("playlist")
+                                .help("Path to iTunes playlist file or Library.xml, or - to read from stdin")
+                                .index(1)
+                                .required(true),
+                        )
+                        .arg(Arg::with_name("format")
+                             .help("Format to export to")
+                             .long("format")
+                             .takes_value(true)
+                             .default_value("m3u8")
+                             .possible_values(export::Format::NAMES)
+                        )
+                        .arg(Arg::with_name("output")
+                             .help("File to write to. If this is not provided the playlist is written to stdout")
+                             .long("output")
+                             .short("o")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("name")
+                             .help("Export the library playlist with this name or folder path instead of every track")
+                             .long("name")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("folder-separator")
+                             .help("Separator placed between folder names in playlist folder paths")
+                             .long("folder-separator")
+                             .takes_value(true)
+                             .default_value(" / ")
+                        )
+                        .arg(Arg::with_name("remap")
+                             .help("Replace the start of track locations, e.g. /Users/me/Music=/mnt/nas/music. Can be repeated")
+                             .long("remap")
+                             .value_name("FROM=TO")
+                             .takes_value(true)
+                             .multiple(true)
+                             .number_of_values(1)
+                        )
+                        .arg(Arg::with_name("music-folder")
+                             .help("Where the library's Music Folder is now, if it has moved")
+                             .long("music-folder")
+                             .takes_value(true)
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("list-playlists")
+                        .about("List the playlists and folders of an iTunes library")
+                        .arg(
+                            Arg::with_name("library")
+                                .help("Path to iTunes Library.xml, or - to read from stdin")
+                                .index(1)
+                                .required(true),
+                        )
+                        .arg(Arg::with_name("include-builtin")
+                             .help("Also list playlists iTunes creates itself, e.g. Library, Music and Downloaded")
+                             .long("include-builtin")
+                        ),
                 ),
         )
         .subcommand(
@@ -54,11 +151,33 @@ fn main() {
                                 .help("Name of the Spotify playlist.")
                                 .index(1)
                                 .required(true),
-                        ),
+                        )
+                        .args(&playlist_settings_args()),
                 )
                 .subcommand(
                     SubCommand::with_name("list-playlists").about("List Spotify playlists."),
                 )
+                .subcommand(
+                    SubCommand::with_name("dedupe-playlist")
+                        .about("Remove duplicate tracks from a Spotify playlist, keeping the first of each.")
+                        .arg(
+                            Arg::with_name("playlist")
+                                .help("Spotify URI or ID of the playlist")
+                                .index(1)
+                                .required(true),
+                        )
+                        .arg(Arg::with_name("dedupe-by")
+                             .help("What makes tracks duplicates: the same Spotify track, or the same title and artist on any album")
+                             .long("dedupe-by")
+                             .takes_value(true)
+                             .default_value("uri")
+                             .possible_values(dedupe::DuplicateKey::NAMES)
+                        )
+                        .arg(Arg::with_name("print-only")
+                             .help("Only list the duplicates, don't remove them")
+                             .long("print-only")
+                        ),
+                )
                 .subcommand(
                     SubCommand::with_name("match-track")
                         .about("Match a track with Spotify.")
@@ -84,23 +203,305 @@ fn main() {
                 .subcommand(
                     SubCommand::with_name("match-playlist")
                         .about("Match an iTunes playlists with tracks on Spotify.")
-                        .arg(
-                            Arg::with_name("playlist")
-                                .help("Path to iTunes playlist file")
-                                .required(true),
-                        )
+                        .args(&playlist_args())
                         .arg(Arg::with_name("print-only")
                              .help("Only print the matched playlist, don't create the Spotify playlist")
                              .long("print-only")
                         )
                         .arg(Arg::with_name("playlist-name")
-                             .help("Creates a Spotify playlist with the specified name. If this is not provided a playlist name is automatically generated")
+                             .help("Creates a Spotify playlist with the specified name. If this is not provided the playlist is named after the source playlist, or --name-template")
                              .long("playlist-name")
                              .takes_value(true)
+                             .conflicts_with_all(&["print-only", "to-library", "name-template"])
+                        )
+                        .arg(name_template_arg())
+                        .arg(Arg::with_name("dedupe-by")
+                             .help("What makes matched tracks duplicates: the same Spotify track, or the same title and artist on any album")
+                             .long("dedupe-by")
+                             .takes_value(true)
+                             .default_value("uri")
+                             .possible_values(dedupe::DuplicateKey::NAMES)
+                        )
+                        .arg(Arg::with_name("keep")
+                             .help("Keep only the first of duplicate tracks, or all of them. Duplicates are listed either way")
+                             .long("keep")
+                             .takes_value(true)
+                             .default_value("all")
+                             .possible_values(dedupe::Keep::NAMES)
+                        )
+                        .arg(Arg::with_name("to-library")
+                             .help("Save the matched tracks to Liked Songs instead of creating a Spotify playlist")
+                             .long("to-library")
+                        )
+                        .args(&playlist_settings_args())
+                        .arg(Arg::with_name("albums")
+                             .help("Also save the albums of the matched tracks to the library")
+                             .long("albums")
+                             .requires("to-library")
+                        )
+                        .args(&journal_args())
+                )
+                .subcommand(
+                    SubCommand::with_name("save-tracks")
+                        .about("Match a playlist with tracks on Spotify and save them to Liked Songs.")
+                        .args(&playlist_args())
+                        .arg(Arg::with_name("print-only")
+                             .help("Only print the matched tracks, don't save them")
+                             .long("print-only")
+                        )
+                        .arg(Arg::with_name("albums")
+                             .help("Also save the albums of the matched tracks to the library")
+                             .long("albums")
                              .conflicts_with("print-only")
                         )
-
-
+                        .args(&journal_args())
+                )
+                .subcommand(
+                    SubCommand::with_name("convert-ratings")
+                        .about("Convert the loved tracks and star ratings of an iTunes library.")
+                        .arg(
+                            Arg::with_name("library")
+                                .help("Path to iTunes Library.xml, or - to read from stdin")
+                                .required(true),
+                        )
+                        .arg(Arg::with_name("loved")
+                             .help("Save loved tracks to Liked Songs")
+                             .long("loved")
+                        )
+                        .arg(Arg::with_name("rating-playlists")
+                             .help("Create a Spotify playlist for each star rating, e.g. \"★★★★★ from iTunes\"")
+                             .long("rating-playlists")
+                        )
+                        .group(ArgGroup::with_name("conversions")
+                               .args(&["loved", "rating-playlists"])
+                               .multiple(true)
+                               .required(true)
+                        )
+                        .arg(Arg::with_name("min-rating")
+                             .help("Only create playlists for tracks rated at least this many stars")
+                             .long("min-rating")
+                             .value_name("STARS")
+                             .takes_value(true)
+                             .requires("rating-playlists")
+                        )
+                        .args(&playlist_settings_args())
+                        .arg(Arg::with_name("name-suffix")
+                             .help("Added to the stars to name the rating playlists")
+                             .long("name-suffix")
+                             .takes_value(true)
+                             .default_value(" from iTunes")
+                        )
+                        .arg(Arg::with_name("print-only")
+                             .help("Only print the matched tracks, don't change the Spotify library")
+                             .long("print-only")
+                        )
+                        .arg(Arg::with_name("match-by")
+                             .help("Match track by track, or match the albums of the tracks first and search only the tracks not found on them")
+                             .long("match-by")
+                             .takes_value(true)
+                             .default_value("track")
+                             .possible_values(matching::MatchMode::NAMES)
+                        )
+                        .arg(workers_arg())
+                        .arg(Arg::with_name("include-kind")
+                             .help("Also match non-music items of this kind, which are skipped by default")
+                             .long("include-kind")
+                             .takes_value(true)
+                             .multiple(true)
+                             .number_of_values(1)
+                             .possible_values(itunes::MediaKind::NON_MUSIC_NAMES)
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("generate")
+                        .about("Generate Spotify playlists from the play statistics of an iTunes library.")
+                        .arg(
+                            Arg::with_name("library")
+                                .help("Path to iTunes Library.xml, or - to read from stdin")
+                                .required(true),
+                        )
+                        .arg(Arg::with_name("rule")
+                             .help("How tracks are chosen and ordered. Can be repeated to generate several playlists")
+                             .long("rule")
+                             .takes_value(true)
+                             .multiple(true)
+                             .number_of_values(1)
+                             .required(true)
+                             .possible_values(generate::Rule::NAMES)
+                        )
+                        .arg(Arg::with_name("limit")
+                             .help("Most tracks in each playlist, or 0 for no limit. Tracks not found on Spotify are passed over for the next ones")
+                             .long("limit")
+                             .takes_value(true)
+                             .default_value("100")
+                        )
+                        .arg(Arg::with_name("min-plays")
+                             .help("Only tracks played at least this many times")
+                             .long("min-plays")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("max-skips")
+                             .help("Only tracks skipped at most this many times")
+                             .long("max-skips")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("added-after")
+                             .help("Only tracks added to the library on or after this date")
+                             .long("added-after")
+                             .value_name("YYYY-MM-DD")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("played-after")
+                             .help("Only tracks last played on or after this date")
+                             .long("played-after")
+                             .value_name("YYYY-MM-DD")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("min-rating")
+                             .help("Only tracks rated at least this many stars")
+                             .long("min-rating")
+                             .value_name("STARS")
+                             .takes_value(true)
+                        )
+                        .args(&playlist_settings_args())
+                        .arg(Arg::with_name("name-prefix")
+                             .help("Prefix added to the name of every Spotify playlist")
+                             .long("name-prefix")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("print-only")
+                             .help("Only print the generated playlists, don't create the Spotify playlists")
+                             .long("print-only")
+                        )
+                        .arg(Arg::with_name("match-by")
+                             .help("Match track by track, or match the albums of the tracks first and search only the tracks not found on them")
+                             .long("match-by")
+                             .takes_value(true)
+                             .default_value("track")
+                             .possible_values(matching::MatchMode::NAMES)
+                        )
+                        .arg(workers_arg())
+                        .arg(Arg::with_name("include-kind")
+                             .help("Also match non-music items of this kind, which are skipped by default")
+                             .long("include-kind")
+                             .takes_value(true)
+                             .multiple(true)
+                             .number_of_values(1)
+                             .possible_values(itunes::MediaKind::NON_MUSIC_NAMES)
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("follow-artists")
+                        .about("Follow the artists of an iTunes library on Spotify.")
+                        .arg(
+                            Arg::with_name("library")
+                                .help("Path to iTunes Library.xml, or - to read from stdin")
+                                .required(true),
+                        )
+                        .arg(Arg::with_name("playlist")
+                             .help("Only follow the artists of the playlist with this name or folder path. Can be repeated")
+                             .long("playlist")
+                             .takes_value(true)
+                             .multiple(true)
+                             .number_of_values(1)
+                        )
+                        .arg(Arg::with_name("folder-separator")
+                             .help("Separator placed between folder names in playlist paths")
+                             .long("folder-separator")
+                             .takes_value(true)
+                             .default_value(" / ")
+                        )
+                        .arg(Arg::with_name("min-tracks")
+                             .help("Only follow artists with at least this many tracks")
+                             .long("min-tracks")
+                             .takes_value(true)
+                             .default_value("1")
+                        )
+                        .arg(Arg::with_name("pick-popular")
+                             .help("Follow the most popular of several Spotify artists with the same name instead of listing them for review")
+                             .long("pick-popular")
+                        )
+                        .arg(Arg::with_name("print-only")
+                             .help("Only print the matched artists, don't follow them")
+                             .long("print-only")
+                        )
+                        .arg(Arg::with_name("include-kind")
+                             .help("Also follow the artists of non-music items of this kind, which are skipped by default")
+                             .long("include-kind")
+                             .takes_value(true)
+                             .multiple(true)
+                             .number_of_values(1)
+                             .possible_values(itunes::MediaKind::NON_MUSIC_NAMES)
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("match-library")
+                        .about("Match the playlists of an iTunes library with tracks on Spotify.")
+                        .arg(
+                            Arg::with_name("library")
+                                .help("Path to iTunes Library.xml, Rhythmbox playlists.xml, Clementine or Strawberry database, or - to read from stdin")
+                                .required(true),
+                        )
+                        .arg(Arg::with_name("format")
+                             .help("Format of the library. If this is not provided it is guessed from the file name")
+                             .long("format")
+                             .takes_value(true)
+                             .possible_values(source::Format::NAMES)
+                        )
+                        .arg(Arg::with_name("playlist")
+                             .help("Only convert the playlist with this name or folder path. Can be repeated")
+                             .long("playlist")
+                             .takes_value(true)
+                             .multiple(true)
+                             .number_of_values(1)
+                        )
+                        .arg(Arg::with_name("print-only")
+                             .help("Only print the matched playlists, don't create the Spotify playlists")
+                             .long("print-only")
+                        )
+                        .arg(Arg::with_name("include-builtin")
+                             .help("Also convert playlists iTunes creates itself, e.g. Library, Music and Downloaded")
+                             .long("include-builtin")
+                        )
+                        .arg(Arg::with_name("skip-smart")
+                             .help("Don't convert smart playlists")
+                             .long("skip-smart")
+                        )
+                        .arg(Arg::with_name("flatten-folders")
+                             .help("Name Spotify playlists after the iTunes playlist only, without its folders")
+                             .long("flatten-folders")
+                             .conflicts_with("name-template")
+                        )
+                        .arg(name_template_arg())
+                        .arg(Arg::with_name("folder-separator")
+                             .help("Separator placed between folder names in Spotify playlist names")
+                             .long("folder-separator")
+                             .takes_value(true)
+                             .default_value(" / ")
+                        )
+                        .args(&playlist_settings_args())
+                        .arg(Arg::with_name("name-prefix")
+                             .help("Prefix added to the name of every Spotify playlist")
+                             .long("name-prefix")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("match-by")
+                             .help("Match track by track, or match the albums of the tracks first and search only the tracks not found on them")
+                             .long("match-by")
+                             .takes_value(true)
+                             .default_value("track")
+                             .possible_values(matching::MatchMode::NAMES)
+                        )
+                        .arg(workers_arg())
+                        .arg(Arg::with_name("include-kind")
+                             .help("Also match non-music items of this kind, which are skipped by default")
+                             .long("include-kind")
+                             .takes_value(true)
+                             .multiple(true)
+                             .number_of_values(1)
+                             .possible_values(itunes::MediaKind::NON_MUSIC_NAMES)
+                        )
+                        .args(&journal_args())
                 ),
         )
         .get_matches();
@@ -109,8 +510,13 @@ fn main() {
 
     debug!("Starting up");
 
-    match matches.subcommand() {
+    let result = match matches.subcommand() {
         ("itunes", Some(itunes_matches)) => match itunes_matches.subcommand() {
+            ("check-files", Some(check_files_matches)) => command::check_files(check_files_matches),
+            ("export", Some(export_matches)) => command::export(export_matches),
+            ("list-playlists", Some(list_playlists_matches)) => {
+                command::list_library_playlists(list_playlists_matches)
+            }
             ("show-playlist", Some(show_playlist_matches)) => {
                 command::show_playlist(show_playlist_matches)
             }
@@ -122,33 +528,179 @@ fn main() {
 
             match spotify_matches.subcommand() {
                 ("auth", Some(_)) => {
-                    command::authenticate(&spotify_client_id, &spotify_client_secret)
+                    command::authenticate(spotify_client_id, spotify_client_secret)
                 }
                 ("create-playlist", Some(spotify_create_playlist_matches)) => {
                     command::create_playlist(
-                        &spotify_client_id,
-                        &spotify_client_secret,
+                        spotify_client_id,
+                        spotify_client_secret,
                         spotify_create_playlist_matches,
                     )
                 }
                 ("list-playlists", Some(_)) => {
-                    command::list_playlists(&spotify_client_id, &spotify_client_secret)
+                    command::list_playlists(spotify_client_id, spotify_client_secret)
+                }
+                ("dedupe-playlist", Some(spotify_dedupe_playlist_matches)) => {
+                    command::dedupe_playlist(
+                        spotify_client_id,
+                        spotify_client_secret,
+                        spotify_dedupe_playlist_matches,
+                    )
                 }
                 ("match-playlist", Some(spotify_match_playlist_matches)) => {
                     command::match_playlist(
-                        &spotify_client_id,
-                        &spotify_client_secret,
+                        spotify_client_id,
+                        spotify_client_secret,
                         spotify_match_playlist_matches,
                     )
                 }
+                ("save-tracks", Some(spotify_save_tracks_matches)) => command::save_tracks(
+                    spotify_client_id,
+                    spotify_client_secret,
+                    spotify_save_tracks_matches,
+                ),
+                ("convert-ratings", Some(spotify_convert_ratings_matches)) => {
+                    command::convert_ratings(
+                        spotify_client_id,
+                        spotify_client_secret,
+                        spotify_convert_ratings_matches,
+                    )
+                }
+                ("generate", Some(spotify_generate_matches)) => command::generate(
+                    spotify_client_id,
+                    spotify_client_secret,
+                    spotify_generate_matches,
+                ),
+                ("follow-artists", Some(spotify_follow_artists_matches)) => {
+                    command::follow_artists(
+                        spotify_client_id,
+                        spotify_client_secret,
+                        spotify_follow_artists_matches,
+                    )
+                }
+                ("match-library", Some(spotify_match_library_matches)) => command::match_library(
+                    spotify_client_id,
+                    spotify_client_secret,
+                    spotify_match_library_matches,
+                ),
                 ("match-track", Some(spotify_match_track_matches)) => command::match_track(
-                    &spotify_client_id,
-                    &spotify_client_secret,
+                    spotify_client_id,
+                    spotify_client_secret,
                     spotify_match_track_matches,
                 ),
                 _ => unreachable!(),
             }
         }
         _ => unreachable!(),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        let mut source = error.source();
+        while let Some(cause) = source {
+            eprintln!("  caused by: {}", cause);
+            source = cause.source();
+        }
+        process::exit(error.exit_code());
     }
 }
+
+/// Arguments of the subcommands that match the tracks of a single playlist.
+fn playlist_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("playlist")
+            .help("Path to iTunes playlist file, CSV, TSV, XSPF, PLS or M3U file, folder of audio files, or - to read from stdin")
+            .required(true),
+        Arg::with_name("format")
+            .help("Format of the playlist file. If this is not provided it is guessed from the file extension, or is folder for a directory")
+            .long("format")
+            .takes_value(true)
+            .possible_values(source::Format::NAMES),
+        Arg::with_name("column")
+            .help("Read a field from a CSV or TSV column given by header or position, e.g. title=Song or artist=2. Can be repeated")
+            .long("column")
+            .value_name("FIELD=COLUMN")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("group-by-album")
+            .help("Order the tracks of a folder album by album, in disc and track number order, instead of by path")
+            .long("group-by-album"),
+        Arg::with_name("min-rating")
+            .help("Only match tracks of an iTunes playlist rated at least this many stars")
+            .long("min-rating")
+            .value_name("STARS")
+            .takes_value(true),
+        Arg::with_name("match-by")
+            .help("Match track by track, or match the albums of the tracks first and search only the tracks not found on them")
+            .long("match-by")
+            .takes_value(true)
+            .default_value("track")
+            .possible_values(matching::MatchMode::NAMES),
+        workers_arg(),
+        Arg::with_name("include-kind")
+            .help("Also match non-music items of this kind, which are skipped by default")
+            .long("include-kind")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .possible_values(itunes::MediaKind::NON_MUSIC_NAMES),
+    ]
+}
+
+/// Argument of the subcommands that name Spotify playlists after source playlists.
+fn name_template_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("name-template")
+        .help("Name of the Spotify playlists, where {source_name}, {folder}, {date}, {count} and {profile} stand for the source playlist name, its folders, today's date, the number of matched tracks and the Spotify profile name")
+        .long("name-template")
+        .env("ISPOT_PLAYLIST_NAME_TEMPLATE")
+        .takes_value(true)
+}
+
+/// Argument of the subcommands that search for tracks on Spotify.
+fn workers_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("workers")
+        .help("Number of tracks to search for at once. All searches share one request rate, and wait together when Spotify asks to slow down")
+        .long("workers")
+        .value_name("N")
+        .takes_value(true)
+        .default_value("4")
+}
+
+/// Arguments of the subcommands whose conversions can be resumed after an interruption.
+fn journal_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("journal")
+            .help("Record the matched tracks and the progress of the Spotify playlists in this file, so an interrupted conversion can be resumed")
+            .long("journal")
+            .value_name("FILE")
+            .takes_value(true),
+        Arg::with_name("resume")
+            .help("Continue the conversion recorded in the journal, without searching its tracks again or creating its playlists twice")
+            .long("resume")
+            .requires("journal"),
+    ]
+}
+
+/// Arguments of the subcommands that create Spotify playlists.
+fn playlist_settings_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("visibility")
+            .help("Who can see the Spotify playlists. Collaborative playlists can be changed by their followers and are never public")
+            .long("visibility")
+            .env("ISPOT_PLAYLIST_VISIBILITY")
+            .takes_value(true)
+            .default_value("private")
+            .possible_values(playlist::Visibility::NAMES),
+        Arg::with_name("description")
+            .help("Description of the Spotify playlists, where {name}, {date}, {matched} and {total} stand for the source playlist name, today's date and its matched and total tracks")
+            .long("description")
+            .env("ISPOT_PLAYLIST_DESCRIPTION")
+            .takes_value(true),
+        Arg::with_name("cover")
+            .help("Cover image of the Spotify playlists: a JPEG or PNG file, artwork for the artwork of the first local audio file, or mosaic for the covers of the first four matched albums")
+            .long("cover")
+            .value_name("FILE|artwork|mosaic")
+            .takes_value(true),
+    ]
+}
@@ -0,0 +1,318 @@
+//! Matching whole albums on Spotify instead of searching track by track.
+//!
+//! Source tracks are grouped by album artist and album, and the albums found on Spotify are
+//! compared with each group by their track listings. Comparing durations as well as titles picks
+//! the right edition, e.g. the original release rather than a remaster with different takes.
+use crate::error::{Error, ErrorKind};
+use crate::source::SourceTrack;
+use rspotify::spotify::model::track::SimplifiedTrack;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Difference in duration up to which a track counts as the same recording.
+const CLOSE_DURATION_MS: u64 = 3_000;
+/// Difference in duration beyond which tracks with the same title are different recordings.
+const MAX_DURATION_DIFFERENCE_MS: u64 = 15_000;
+
+/// How tracks are searched on Spotify.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatchMode {
+    /// Search every track by its name, artist and album.
+    Track,
+    /// Search the albums of the tracks and match tracks within their album's listing, falling back
+    /// to searching the tracks that aren't on any album found.
+    Album,
+}
+
+impl MatchMode {
+    /// Names accepted on the command line.
+    pub const NAMES: &'static [&'static str] = &["album", "track"];
+}
+
+impl FromStr for MatchMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "album" => Ok(MatchMode::Album),
+            "track" => Ok(MatchMode::Track),
+            _ => Err(Error::new(ErrorKind::Config(format!(
+                "unknown match mode {}",
+                s
+            )))),
+        }
+    }
+}
+
+/// The tracks of a source playlist that are on the same album.
+#[derive(Debug, PartialEq)]
+pub struct SourceAlbum {
+    pub name: String,
+    /// The album artist, or the artist of the first track if the source has no album artists.
+    pub artist: String,
+    /// Indices of the tracks in the source playlist, in playlist order.
+    pub tracks: Vec<usize>,
+}
+
+/// Group the tracks of a playlist by album artist and album.
+///
+/// Tracks with a Spotify URI or ISRC are left out, as they are found without a search, and so are
+/// albums with a single track, which are cheaper to search track by track.
+pub fn group_albums(tracks: &[SourceTrack]) -> Vec<SourceAlbum> {
+    let mut albums: Vec<SourceAlbum> = Vec::new();
+    let mut album_indices: HashMap<(String, String), usize> = HashMap::new();
+    for (i, track) in tracks.iter().enumerate() {
+        if track.spotify_uri.is_some() || track.isrc.is_some() {
+            continue;
+        }
+        let artist = track.album_artist.as_ref().or(track.artist.as_ref());
+        let (artist, name) = match (artist, track.album.as_ref()) {
+            (Some(artist), Some(name)) => (artist, name),
+            _ => continue,
+        };
+        let key = (artist.to_lowercase(), name.to_lowercase());
+        let index = *album_indices.entry(key).or_insert_with(|| {
+            albums.push(SourceAlbum {
+                name: name.clone(),
+                artist: artist.clone(),
+                tracks: Vec::new(),
+            });
+            albums.len() - 1
+        });
+        albums[index].tracks.push(i);
+    }
+    albums.retain(|album| album.tracks.len() > 1);
+    albums
+}
+
+/// A track in the listing of a Spotify album.
+#[derive(Clone, Debug, Default)]
+pub struct AlbumTrack {
+    pub name: String,
+    /// Duration of the track in milliseconds.
+    pub duration: u64,
+    pub disc_number: u32,
+    pub track_number: u32,
+    pub uri: String,
+}
+
+impl<'a> From<&'a SimplifiedTrack> for AlbumTrack {
+    fn from(track: &'a SimplifiedTrack) -> AlbumTrack {
+        AlbumTrack {
+            name: track.name.clone(),
+            duration: u64::from(track.duration_ms),
+            disc_number: track.disc_number.max(1) as u32,
+            track_number: track.track_number,
+            uri: track.uri.clone(),
+        }
+    }
+}
+
+/// The tracks of a source album found in the listing of an edition.
+#[derive(Debug, PartialEq)]
+pub struct EditionMatch {
+    /// Index of the edition among the listings compared.
+    pub edition: usize,
+    /// The Spotify URI for each source track, `None` for tracks not on the edition.
+    pub uris: Vec<Option<String>>,
+    score: u32,
+}
+
+impl EditionMatch {
+    /// Number of source tracks found on the edition.
+    pub fn matched(&self) -> usize {
+        self.uris.iter().filter(|uri| uri.is_some()).count()
+    }
+}
+
+/// Compare the source tracks of an album with the listings of the editions found on Spotify, and
+/// pick the edition that matches the most tracks most closely.
+///
+/// Of equally good editions the one with the number of tracks closest to the source is picked, so
+/// that a deluxe edition is only used for the bonus tracks it has.
+pub fn best_edition(source: &[&SourceTrack], listings: &[Vec<AlbumTrack>]) -> Option<EditionMatch> {
+    listings
+        .iter()
+        .enumerate()
+        .map(|(edition, listing)| {
+            let mut edition_match = compare_listing(source, listing);
+            edition_match.edition = edition;
+            (edition_match, listing.len().abs_diff(source.len()))
+        })
+        .filter(|(edition_match, _)| edition_match.score > 0)
+        // `min_by_key` keeps the first of equal editions, which is the most relevant search result.
+        .min_by_key(|(edition_match, size_difference)| {
+            (std::cmp::Reverse(edition_match.score), *size_difference)
+        })
+        .map(|(edition_match, _)| edition_match)
+}
+
+/// Find each source track in a listing, using every track of the listing at most once.
+fn compare_listing(source: &[&SourceTrack], listing: &[AlbumTrack]) -> EditionMatch {
+    let mut used = vec![false; listing.len()];
+    let mut uris = Vec::with_capacity(source.len());
+    let mut score = 0;
+    for track in source {
+        let best = listing
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| !used[i])
+            .filter_map(|(i, album_track)| track_score(track, album_track).map(|s| (i, s)))
+            // The first of equally good tracks, as reissues often append alternate takes.
+            .fold(None, |best: Option<(usize, u32)>, (i, s)| match best {
+                Some((_, best_score)) if best_score >= s => best,
+                _ => Some((i, s)),
+            });
+        match best {
+            Some((i, track_score)) => {
+                used[i] = true;
+                score += track_score;
+                uris.push(Some(listing[i].uri.clone()));
+            }
+            None => uris.push(None),
+        }
+    }
+    EditionMatch {
+        edition: 0,
+        uris,
+        score,
+    }
+}
+
+/// How closely a track of a listing matches a source track, `None` if it's a different track.
+fn track_score(track: &SourceTrack, album_track: &AlbumTrack) -> Option<u32> {
+    if normalize_title(&track.name) != normalize_title(&album_track.name) {
+        return None;
+    }
+    let mut score = match track.duration {
+        Some(duration) => match duration.abs_diff(album_track.duration) {
+            difference if difference <= CLOSE_DURATION_MS => 3,
+            difference if difference <= MAX_DURATION_DIFFERENCE_MS => 1,
+            _ => return None,
+        },
+        None => 1,
+    };
+    if track.track_number == Some(album_track.track_number)
+        && track.disc_number.unwrap_or(1) == album_track.disc_number
+    {
+        score += 1;
+    }
+    Some(score)
+}
+
+/// Reduce a title to what editions agree on, dropping suffixes like `(Remastered 2011)` or
+/// `- Live`, case and punctuation.
+pub(crate) fn normalize_title(title: &str) -> String {
+    let lowercase = title.to_lowercase();
+    let base = [" (", " [", " - "]
+        .iter()
+        .filter_map(|separator| lowercase.find(separator))
+        .min()
+        .map_or(lowercase.as_str(), |end| &lowercase[..end]);
+    let base = if base.trim().is_empty() {
+        lowercase.as_str()
+    } else {
+        base
+    };
+    base.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{best_edition, group_albums, normalize_title, AlbumTrack};
+    use crate::source::SourceTrack;
+
+    fn source_track(name: &str, duration: u64, number: u32) -> SourceTrack {
+        SourceTrack {
+            name: name.to_string(),
+            artist: Some("Radiohead".to_string()),
+            album: Some("OK Computer".to_string()),
+            duration: Some(duration),
+            track_number: Some(number),
+            ..SourceTrack::default()
+        }
+    }
+
+    fn album_track(name: &str, duration: u64, number: u32, uri: &str) -> AlbumTrack {
+        AlbumTrack {
+            name: name.to_string(),
+            duration,
+            disc_number: 1,
+            track_number: number,
+            uri: uri.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_normalize_title() {
+        assert_eq!(normalize_title("Paranoid Android"), "paranoid android");
+        assert_eq!(
+            normalize_title("Paranoid Android - Remastered"),
+            "paranoid android"
+        );
+        assert_eq!(normalize_title("Karma Police (Live)"), "karma police");
+        assert_eq!(normalize_title("Don't Stop Me Now"), "don t stop me now");
+        assert_eq!(normalize_title("(Nice Dream)"), "nice dream");
+    }
+
+    #[test]
+    fn test_group_albums() {
+        let mut tracks = vec![
+            source_track("Airbag", 284_000, 1),
+            source_track("Lucky", 259_000, 11),
+            SourceTrack {
+                name: "Teardrop".to_string(),
+                artist: Some("Massive Attack".to_string()),
+                album: Some("Mezzanine".to_string()),
+                ..SourceTrack::default()
+            },
+            source_track("Let Down", 299_000, 5),
+        ];
+        tracks[3].isrc = Some("GBAYE9700105".to_string());
+        let albums = group_albums(&tracks);
+        assert_eq!(albums.len(), 1);
+        assert_eq!(albums[0].name, "OK Computer");
+        assert_eq!(albums[0].artist, "Radiohead");
+        assert_eq!(albums[0].tracks, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_best_edition() {
+        let source = [
+            source_track("Airbag", 284_000, 1),
+            source_track("Paranoid Android", 387_000, 2),
+            source_track("Unknown Demo", 200_000, 13),
+        ];
+        let source: Vec<&SourceTrack> = source.iter().collect();
+        let original = vec![
+            album_track("Airbag", 284_373, 1, "spotify:track:original1"),
+            album_track("Paranoid Android", 387_213, 2, "spotify:track:original2"),
+            album_track("Lucky", 259_000, 11, "spotify:track:original11"),
+        ];
+        let mut deluxe = original.clone();
+        for track in &mut deluxe {
+            track.uri = track.uri.replace("original", "deluxe");
+        }
+        deluxe.extend((4..20).map(|n| album_track("Bonus", 100_000, n, "spotify:track:bonus")));
+        let live = vec![
+            album_track("Airbag - Live", 312_000, 1, "spotify:track:live1"),
+            album_track("Paranoid Android - Live", 401_000, 2, "spotify:track:live2"),
+        ];
+
+        let edition_match = best_edition(&source, &[live, deluxe, original]).unwrap();
+        assert_eq!(edition_match.edition, 2);
+        assert_eq!(edition_match.matched(), 2);
+        assert_eq!(
+            edition_match.uris,
+            vec![
+                Some("spotify:track:original1".to_string()),
+                Some("spotify:track:original2".to_string()),
+                None
+            ]
+        );
+        assert_eq!(best_edition(&source, &[]), None);
+    }
+}
@@ -1,6 +1,12 @@
+use crate::dedupe::Duplicate;
+use crate::itunes::location::LocalFile;
+use crate::itunes::{MediaKind, Playlist, Track};
 use crate::types::DisplayTrack;
 use prettytable::{format, Cell, Row, Table};
+use rspotify::spotify::model::artist::FullArtist;
 use rspotify::spotify::model::playlist::{FullPlaylist, SimplifiedPlaylist};
+use std::collections::BTreeMap;
+use std::fmt;
 
 pub fn tabulate_playlist(playlist: &FullPlaylist) {
     let mut table = Table::new();
@@ -31,22 +37,79 @@ pub fn tabulate_playlists(playlists: &[SimplifiedPlaylist]) {
     table.printstd();
 }
 
+/// Print Spotify artists with their popularity, e.g. the candidates for an ambiguous name.
+pub fn tabulate_artists(artists: &[&FullArtist]) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+
+    table.set_titles(Row::new(vec![
+        Cell::new("Name"),
+        Cell::new("Popularity"),
+        Cell::new("Spotify URI"),
+    ]));
+
+    for artist in artists {
+        table.add_row(Row::new(vec![
+            Cell::new(&artist.name),
+            Cell::new(&artist.popularity.to_string()),
+            Cell::new(&artist.uri),
+        ]));
+    }
+
+    table.printstd();
+}
+
+/// Print the playlists of an iTunes library, indented by folder.
+pub fn tabulate_library_playlists(library: &Playlist, include_builtin: bool) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+
+    table.set_titles(Row::new(vec![
+        Cell::new("Name"),
+        Cell::new("Type"),
+        Cell::new("Tracks"),
+    ]));
+
+    for playlist in &library.playlists {
+        if playlist.is_builtin() && !include_builtin {
+            continue;
+        }
+        let depth = library.folder_path(playlist).len();
+        let kind = if playlist.folder {
+            "folder"
+        } else if playlist.is_builtin() {
+            "built-in"
+        } else if playlist.smart {
+            "smart"
+        } else {
+            "playlist"
+        };
+        table.add_row(Row::new(vec![
+            Cell::new(&format!("{}{}", "  ".repeat(depth), playlist.name)),
+            Cell::new(kind),
+            Cell::new(&playlist.track_ids.len().to_string()),
+        ]));
+    }
+
+    table.printstd();
+}
+
 pub fn tabulate_track<T: DisplayTrack>(track: &T) {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
 
-    table.add_row(Row::new(vec![Cell::new("Name"), Cell::new(&track.name())]));
+    table.add_row(Row::new(vec![Cell::new("Name"), Cell::new(track.name())]));
     table.add_row(Row::new(vec![
         Cell::new("Arist"),
         Cell::new(&track.artist()),
     ]));
     table.add_row(Row::new(vec![
         Cell::new("Album"),
-        Cell::new(&track.album().unwrap_or(&"".to_string())),
+        Cell::new(track.album().unwrap_or(&"".to_string())),
     ]));
     table.add_row(Row::new(vec![
         Cell::new("Spotify URI"),
-        Cell::new(&track.spotify_uri()),
+        Cell::new(track.spotify_uri()),
     ]));
 
     table.printstd();
@@ -65,12 +128,89 @@ pub fn tabulate_tracks<T: DisplayTrack>(tracks: &[T]) {
 
     for track in tracks {
         table.add_row(Row::new(vec![
-            Cell::new(&track.name()),
+            Cell::new(track.name()),
+            Cell::new(&track.artist()),
+            Cell::new(track.album().unwrap_or(&"".to_string())),
+            Cell::new(track.spotify_uri()),
+        ]));
+    }
+
+    table.printstd();
+}
+
+/// Print the duplicates of a playlist with the position of the track they duplicate, counting
+/// from 1.
+pub fn tabulate_duplicates<T: DisplayTrack>(tracks: &[T], duplicates: &[Duplicate]) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+
+    table.set_titles(Row::new(vec![
+        Cell::new("Position"),
+        Cell::new("Name"),
+        Cell::new("Artist"),
+        Cell::new("Duplicate of"),
+    ]));
+
+    for duplicate in duplicates {
+        let track = &tracks[duplicate.index];
+        table.add_row(Row::new(vec![
+            Cell::new(&(duplicate.index + 1).to_string()),
+            Cell::new(track.name()),
             Cell::new(&track.artist()),
-            Cell::new(&track.album().unwrap_or(&"".to_string())),
-            Cell::new(&track.spotify_uri()),
+            Cell::new(&(duplicate.first + 1).to_string()),
+        ]));
+    }
+
+    table.printstd();
+}
+
+/// Print tracks with their local file and whether it exists.
+pub fn tabulate_local_files(files: &[(&Track, LocalFile)]) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+
+    table.set_titles(Row::new(vec![
+        Cell::new("Name"),
+        Cell::new("Path"),
+        Cell::new("Status"),
+    ]));
+
+    for (track, file) in files {
+        let status = if file.exists { "found" } else { "missing" };
+        table.add_row(Row::new(vec![
+            Cell::new(&track.name),
+            Cell::new(&file.path.to_string_lossy()),
+            Cell::new(status),
         ]));
     }
 
     table.printstd();
 }
+
+pub fn print_track_warnings<W: fmt::Display>(warnings: &[W]) {
+    if warnings.is_empty() {
+        return;
+    }
+
+    println!("skipped tracks: {}", warnings.len());
+    for warning in warnings {
+        println!("  {}", warning);
+    }
+}
+
+/// Print how many non-music items were left out of a match, per kind.
+pub fn print_skipped_kinds(tracks: &[&Track]) {
+    if tracks.is_empty() {
+        return;
+    }
+
+    let mut counts: BTreeMap<MediaKind, usize> = BTreeMap::new();
+    for track in tracks {
+        *counts.entry(track.media_kind()).or_insert(0) += 1;
+    }
+
+    println!("skipped non-music items: {}", tracks.len());
+    for (kind, count) in counts {
+        println!("  {}: {}", kind.name(), count);
+    }
+}
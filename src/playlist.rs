@@ -0,0 +1,340 @@
+//! Settings of the Spotify playlists created from a source playlist: who can see them, how they
+//! are described and their cover.
+use crate::cover::{self, CoverSource};
+use crate::error::{Error, ErrorKind};
+use chrono::Local;
+use std::str::FromStr;
+
+/// Longest description Spotify accepts, in characters.
+const MAX_DESCRIPTION_LENGTH: usize = 300;
+
+/// Who can see and change a Spotify playlist.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Visibility {
+    /// Only the user can see the playlist.
+    #[default]
+    Private,
+    /// The playlist is shown on the user's profile.
+    Public,
+    /// Followers of the playlist can change it. Collaborative playlists are never public.
+    Collaborative,
+}
+
+impl Visibility {
+    /// Names accepted on the command line.
+    pub const NAMES: &'static [&'static str] = &["private", "public", "collaborative"];
+
+    pub fn is_public(self) -> bool {
+        self == Visibility::Public
+    }
+
+    pub fn is_collaborative(self) -> bool {
+        self == Visibility::Collaborative
+    }
+}
+
+impl FromStr for Visibility {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "private" => Ok(Visibility::Private),
+            "public" => Ok(Visibility::Public),
+            "collaborative" => Ok(Visibility::Collaborative),
+            _ => Err(Error::new(ErrorKind::Config(format!(
+                "unknown playlist visibility {}",
+                s
+            )))),
+        }
+    }
+}
+
+/// How the Spotify playlists of a command are created.
+#[derive(Clone, Debug, Default)]
+pub struct PlaylistSettings {
+    /// Template of the playlist names, see [`render_name`]. Commands have their own default.
+    pub name: Option<String>,
+    pub visibility: Visibility,
+    /// Template of the playlist description, see [`render_description`].
+    pub description: Option<String>,
+    /// Where the cover image comes from, or `None` to leave the cover Spotify makes.
+    pub cover: Option<CoverSource>,
+}
+
+impl PlaylistSettings {
+    /// Check the templates and the cover image file, so a mistake is reported before any track is
+    /// matched.
+    pub fn new(
+        name: Option<String>,
+        visibility: Visibility,
+        description: Option<String>,
+        cover: Option<CoverSource>,
+    ) -> Result<Self, Error> {
+        let settings = PlaylistSettings {
+            name,
+            visibility,
+            description,
+            cover,
+        };
+        let values = NameValues {
+            source_name: "",
+            folder: "",
+            date: "",
+            count: 0,
+            profile: None,
+        };
+        settings.name(&values, "")?;
+        settings.description("", 0, 0)?;
+        if let Some(CoverSource::File(ref path)) = settings.cover {
+            cover::load_image(path)?;
+        }
+        Ok(settings)
+    }
+
+    /// Whether the name template refers to the Spotify profile, which takes an API call to look
+    /// up.
+    pub fn needs_profile(&self) -> bool {
+        self.name
+            .as_ref()
+            .is_some_and(|template| template.contains("{profile}"))
+    }
+
+    /// The name of a playlist, or `default` without a name template or if the template leaves the
+    /// name empty.
+    pub fn name(&self, values: &NameValues, default: &str) -> Result<String, Error> {
+        match self.name {
+            Some(ref template) => {
+                let name = render_name(template, values)?;
+                if name.trim().is_empty() {
+                    Ok(default.to_string())
+                } else {
+                    Ok(name)
+                }
+            }
+            None => Ok(default.to_string()),
+        }
+    }
+
+    /// The description of a playlist converted today from the source playlist `name`.
+    pub fn description(
+        &self,
+        name: &str,
+        matched: usize,
+        total: usize,
+    ) -> Result<Option<String>, Error> {
+        let values = DescriptionValues {
+            name,
+            date: &today(),
+            matched,
+            total,
+        };
+        self.description
+            .as_ref()
+            .map(|template| render_description(template, &values))
+            .transpose()
+    }
+}
+
+/// Today's date as used in templates, e.g. `2019-10-01`.
+pub fn today() -> String {
+    Local::now().format("%Y-%m-%d").to_string()
+}
+
+/// What a name template can refer to.
+#[derive(Debug)]
+pub struct NameValues<'a> {
+    /// Name of the source playlist, without its folders.
+    pub source_name: &'a str,
+    /// Folders containing the source playlist, outermost first and joined by a separator.
+    pub folder: &'a str,
+    /// Date of the conversion.
+    pub date: &'a str,
+    /// Number of tracks found on Spotify.
+    pub count: usize,
+    /// Display name of the Spotify user, if it was looked up.
+    pub profile: Option<&'a str>,
+}
+
+/// Fill in a name template like `{folder} - {source_name} ({date})`.
+pub fn render_name(template: &str, values: &NameValues) -> Result<String, Error> {
+    render(template, "name", |placeholder| match placeholder {
+        "source_name" => Some(values.source_name.to_string()),
+        "folder" => Some(values.folder.to_string()),
+        "date" => Some(values.date.to_string()),
+        "count" => Some(values.count.to_string()),
+        "profile" => Some(values.profile.unwrap_or_default().to_string()),
+        _ => None,
+    })
+}
+
+/// What a description template can refer to.
+#[derive(Debug)]
+pub struct DescriptionValues<'a> {
+    /// Name of the source playlist.
+    pub name: &'a str,
+    /// Date of the conversion.
+    pub date: &'a str,
+    /// Number of tracks found on Spotify.
+    pub matched: usize,
+    /// Number of tracks of the source playlist.
+    pub total: usize,
+}
+
+/// Fill in a description template like `Imported from iTunes playlist {name} on {date},
+/// {matched}/{total} tracks`.
+///
+/// The description is cut to the length Spotify accepts.
+pub fn render_description(template: &str, values: &DescriptionValues) -> Result<String, Error> {
+    let description = render(template, "description", |placeholder| match placeholder {
+        "name" => Some(values.name.to_string()),
+        "date" => Some(values.date.to_string()),
+        "matched" => Some(values.matched.to_string()),
+        "total" => Some(values.total.to_string()),
+        _ => None,
+    })?;
+    Ok(description.chars().take(MAX_DESCRIPTION_LENGTH).collect())
+}
+
+/// Replace the `{placeholder}`s of a template by their values. `{{` and `}}` stand for literal
+/// braces.
+fn render<F>(template: &str, kind: &str, value: F) -> Result<String, Error>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let invalid = |reason: &str| {
+        Error::new(ErrorKind::Config(format!(
+            "invalid {} template {}: {}",
+            kind, template, reason
+        )))
+    };
+    let mut rendered = String::with_capacity(template.len());
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                rendered.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                rendered.push('}');
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let end = rest.find('}').ok_or_else(|| invalid("unclosed {"))?;
+                let placeholder = &rest[..end];
+                let value = value(placeholder)
+                    .ok_or_else(|| invalid(&format!("unknown placeholder {{{}}}", placeholder)))?;
+                rendered.push_str(&value);
+                chars = rest[end + 1..].chars();
+            }
+            '}' => return Err(invalid("unmatched }")),
+            c => rendered.push(c),
+        }
+    }
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        render_description, render_name, DescriptionValues, NameValues, PlaylistSettings,
+        Visibility,
+    };
+    use crate::cover::CoverSource;
+
+    #[test]
+    fn test_render_description() {
+        let values = DescriptionValues {
+            name: "Road Trip",
+            date: "2019-10-01",
+            matched: 42,
+            total: 50,
+        };
+        assert_eq!(
+            render_description(
+                "Imported from iTunes playlist {name} on {date}, {matched}/{total} tracks",
+                &values
+            )
+            .unwrap(),
+            "Imported from iTunes playlist Road Trip on 2019-10-01, 42/50 tracks"
+        );
+        assert_eq!(
+            render_description("{{name}} is {name}", &values).unwrap(),
+            "{name} is Road Trip"
+        );
+        assert!(render_description("{album}", &values).is_err());
+        assert!(render_description("{name", &values).is_err());
+        assert!(render_description("name}", &values).is_err());
+        assert_eq!(
+            render_description(&"x".repeat(400), &values).unwrap().len(),
+            300
+        );
+    }
+
+    #[test]
+    fn test_playlist_settings() {
+        assert!(PlaylistSettings::new(
+            None,
+            Visibility::Public,
+            Some("{tracks}".to_string()),
+            None
+        )
+        .is_err());
+        assert!(PlaylistSettings::new(
+            None,
+            Visibility::Public,
+            None,
+            Some(CoverSource::File("/nonexistent/cover.jpg".to_string()))
+        )
+        .is_err());
+        let settings = PlaylistSettings::new(None, Visibility::Public, None, None).unwrap();
+        assert_eq!(settings.description("Road Trip", 1, 2).unwrap(), None);
+        assert_eq!(
+            "collaborative".parse::<Visibility>().unwrap(),
+            Visibility::Collaborative
+        );
+        assert!("shared".parse::<Visibility>().is_err());
+    }
+
+    #[test]
+    fn test_render_name() {
+        let values = NameValues {
+            source_name: "Running",
+            folder: "Workout",
+            date: "2019-10-01",
+            count: 12,
+            profile: Some("Quiran"),
+        };
+        assert_eq!(
+            render_name("{folder} - {source_name} ({count} tracks, {date})", &values).unwrap(),
+            "Workout - Running (12 tracks, 2019-10-01)"
+        );
+        assert_eq!(
+            render_name("{profile}'s {source_name}", &values).unwrap(),
+            "Quiran's Running"
+        );
+        assert!(render_name("{name}", &values).is_err());
+
+        assert!(PlaylistSettings::new(
+            Some("{matched}".to_string()),
+            Visibility::Private,
+            None,
+            None
+        )
+        .is_err());
+        let settings = PlaylistSettings::new(
+            Some("{folder}".to_string()),
+            Visibility::Private,
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(!settings.needs_profile());
+        let top_level = NameValues {
+            folder: "",
+            ..values
+        };
+        assert_eq!(settings.name(&top_level, "Running").unwrap(), "Running");
+    }
+}
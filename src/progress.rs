@@ -0,0 +1,235 @@
+//! Progress of matching many tracks: a progress bar on a terminal, or a log line every so often
+//! when stderr goes to a file.
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time between progress log lines when stderr is not a terminal.
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+/// Width of the progress bar, in characters.
+const BAR_WIDTH: usize = 30;
+
+/// The progress being shown, if any. Log records and rate limit sleeps draw around it.
+static CURRENT: Mutex<Option<State>> = Mutex::new(None);
+
+#[derive(Debug)]
+struct State {
+    total: usize,
+    processed: usize,
+    matched: usize,
+    /// Tracks processed without searching, which are left out of the ETA.
+    journaled: usize,
+    started: Instant,
+    /// Seconds left of the rate limit sleep in progress.
+    sleeping: Option<u64>,
+    terminal: bool,
+    last_logged: Instant,
+}
+
+impl State {
+    /// Estimated time until every track is processed, from the rate of the tracks searched so
+    /// far.
+    fn eta(&self, now: Instant) -> Option<Duration> {
+        let searched = self.processed - self.journaled;
+        if searched == 0 {
+            return None;
+        }
+        let remaining = (self.total - self.processed) as u32;
+        Some(now.duration_since(self.started) / searched as u32 * remaining)
+    }
+
+    /// The progress as a line of text, without the bar.
+    fn summary(&self, now: Instant) -> String {
+        let mut summary = format!(
+            "{}/{} tracks, {} matched, {} not found",
+            self.processed,
+            self.total,
+            self.matched,
+            self.processed - self.matched
+        );
+        if let Some(eta) = self.eta(now) {
+            summary.push_str(&format!(", ETA {}", format_duration(eta)));
+        }
+        if let Some(seconds) = self.sleeping {
+            summary.push_str(&format!(
+                ", rate limited for {}",
+                format_duration(Duration::from_secs(seconds))
+            ));
+        }
+        summary
+    }
+
+    fn bar(&self) -> String {
+        let filled = (self.processed * BAR_WIDTH)
+            .checked_div(self.total)
+            .unwrap_or(BAR_WIDTH);
+        format!("[{}{}]", "#".repeat(filled), "-".repeat(BAR_WIDTH - filled))
+    }
+
+    fn draw(&self) {
+        if self.terminal {
+            eprint!("\r\x1b[K{} {}", self.bar(), self.summary(Instant::now()));
+            let _ = io::stderr().flush();
+        }
+    }
+}
+
+/// Progress of matching tracks, shown until it is dropped.
+#[derive(Debug)]
+pub(crate) struct MatchProgress;
+
+impl MatchProgress {
+    /// Start showing the progress of matching `total` tracks.
+    pub fn start(total: usize) -> MatchProgress {
+        let now = Instant::now();
+        let state = State {
+            total,
+            processed: 0,
+            matched: 0,
+            journaled: 0,
+            started: now,
+            sleeping: None,
+            terminal: io::stderr().is_terminal(),
+            last_logged: now,
+        };
+        state.draw();
+        *current() = Some(state);
+        MatchProgress
+    }
+
+    /// Count a track as processed.
+    pub fn track(&self, matched: bool) {
+        self.update(matched, false);
+    }
+
+    /// Count a track whose match was in the journal as processed.
+    pub fn journaled_track(&self, matched: bool) {
+        self.update(matched, true);
+    }
+
+    fn update(&self, matched: bool, journaled: bool) {
+        let mut current = current();
+        let state = match *current {
+            Some(ref mut state) => state,
+            None => return,
+        };
+        state.processed += 1;
+        state.matched += usize::from(matched);
+        state.journaled += usize::from(journaled);
+        if state.terminal {
+            state.draw();
+            return;
+        }
+        let now = Instant::now();
+        if now.duration_since(state.last_logged) >= LOG_INTERVAL {
+            state.last_logged = now;
+            let summary = state.summary(now);
+            // Logging draws around the progress, so it can't be locked meanwhile.
+            drop(current);
+            info!("matching: {}", summary);
+        }
+    }
+}
+
+impl Drop for MatchProgress {
+    fn drop(&mut self) {
+        if let Some(state) = current().take() {
+            if state.terminal {
+                eprint!("\r\x1b[K");
+                let _ = io::stderr().flush();
+            }
+        }
+    }
+}
+
+/// Run `f`, which writes to stderr, with the progress bar out of its way.
+pub(crate) fn suspend<F: FnOnce()>(f: F) {
+    let current = current();
+    match *current {
+        Some(ref state) if state.terminal => {
+            eprint!("\r\x1b[K");
+            f();
+            state.draw();
+        }
+        _ => f(),
+    }
+}
+
+/// Wait out a rate limit, counting the seconds down in the progress bar.
+pub(crate) fn rate_limit_sleep(duration: Duration) {
+    for remaining in (1..=duration.as_secs()).rev() {
+        set_sleeping(Some(remaining));
+        thread::sleep(Duration::from_secs(1));
+    }
+    set_sleeping(None);
+    thread::sleep(Duration::from_nanos(u64::from(duration.subsec_nanos())));
+}
+
+fn set_sleeping(sleeping: Option<u64>) {
+    if let Some(ref mut state) = *current() {
+        state.sleeping = sleeping;
+        state.draw();
+    }
+}
+
+/// The progress being shown. A panic while it was locked leaves it usable, as it is only shown.
+fn current() -> MutexGuard<'static, Option<State>> {
+    CURRENT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A duration rounded to what is worth showing, e.g. `1h 05m`, `4m 10s` or `25s`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_duration, State};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(25_900)), "25s");
+        assert_eq!(format_duration(Duration::from_secs(250)), "4m 10s");
+        assert_eq!(format_duration(Duration::from_secs(3900)), "1h 05m");
+    }
+
+    #[test]
+    fn test_summary() {
+        let started = Instant::now();
+        let mut state = State {
+            total: 100,
+            processed: 30,
+            matched: 25,
+            journaled: 10,
+            started,
+            sleeping: None,
+            terminal: false,
+            last_logged: started,
+        };
+        // 20 tracks searched in 40 seconds leaves 70 tracks for 140 seconds.
+        let now = started + Duration::from_secs(40);
+        assert_eq!(
+            state.summary(now),
+            "30/100 tracks, 25 matched, 5 not found, ETA 2m 20s"
+        );
+        assert_eq!(
+            state.bar(),
+            format!("[{}{}]", "#".repeat(9), "-".repeat(21))
+        );
+
+        state.processed = 10;
+        state.matched = 10;
+        state.sleeping = Some(25);
+        assert_eq!(
+            state.summary(now),
+            "10/100 tracks, 10 matched, 0 not found, rate limited for 25s"
+        );
+    }
+}
@@ -0,0 +1,456 @@
+//! Playlists from sources other than iTunes, converted to tracks that can be matched on Spotify.
+//!
+//! Playlist formats like CSV, XSPF, PLS and M3U hold a single playlist, as does a folder of audio
+//! files. The databases of other players, e.g. Rhythmbox and Clementine, hold a library of named
+//! playlists like iTunes does.
+use crate::error::{Error, ErrorKind};
+use crate::itunes;
+use crate::itunes::location::decode_location;
+use crate::types;
+use crate::util;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub mod clementine;
+pub mod csv;
+pub mod folder;
+pub mod m3u;
+pub mod pls;
+pub mod rhythmbox;
+pub mod xspf;
+
+/// A track to match on Spotify, whatever playlist it came from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceTrack {
+    pub name: String,
+    pub artist: Option<String>,
+    /// The artist of the whole album, e.g. `Various Artists` on compilations.
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub disc_number: Option<u32>,
+    pub track_number: Option<u32>,
+    /// Duration of the track in milliseconds.
+    pub duration: Option<u64>,
+    pub isrc: Option<String>,
+    /// A `spotify:track:` URI, if the source already knows the Spotify track.
+    pub spotify_uri: Option<String>,
+    /// The local audio file of the track, if the source knows it.
+    pub path: Option<PathBuf>,
+}
+
+impl<'a> From<&'a itunes::Track> for SourceTrack {
+    fn from(track: &'a itunes::Track) -> SourceTrack {
+        SourceTrack {
+            name: track.name.clone(),
+            artist: track.artist.clone(),
+            album_artist: track.album_artist.clone(),
+            album: track.album.clone(),
+            disc_number: track.disc_number,
+            track_number: track.track_number,
+            duration: track.total_time,
+            isrc: None,
+            spotify_uri: None,
+            path: track.location.as_deref().and_then(decode_location),
+        }
+    }
+}
+
+impl types::DisplayTrack for SourceTrack {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn artist(&self) -> String {
+        self.artist.clone().unwrap_or_default()
+    }
+
+    fn album(&self) -> Option<&String> {
+        self.album.as_ref()
+    }
+
+    fn spotify_uri(&self) -> &str {
+        self.spotify_uri.as_deref().unwrap_or("n/a")
+    }
+}
+
+/// The tracks of a playlist, in playlist order.
+#[derive(Debug, Default)]
+pub struct SourcePlaylist {
+    /// The name of the playlist, if the source has one.
+    pub name: Option<String>,
+    pub tracks: Vec<SourceTrack>,
+    /// Entries that couldn't be read and were left out of `tracks`.
+    pub warnings: Vec<SourceWarning>,
+}
+
+/// The named playlists of another player's library.
+#[derive(Debug, Default)]
+pub struct SourceLibrary {
+    pub playlists: Vec<SourcePlaylist>,
+    /// Playlists that couldn't be read and were left out of `playlists`.
+    pub warnings: Vec<SourceWarning>,
+}
+
+/// An entry of a playlist file that was skipped, e.g. a CSV row without a title.
+#[derive(Clone, Debug)]
+pub struct SourceWarning {
+    /// Where the entry is in the file, e.g. `row 12`.
+    pub position: String,
+    pub message: String,
+}
+
+impl fmt::Display for SourceWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+/// The format of a playlist file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// A Clementine or Strawberry database.
+    Clementine,
+    Csv,
+    /// A folder of audio files.
+    Folder,
+    Itunes,
+    M3u,
+    Pls,
+    /// A Rhythmbox `playlists.xml`.
+    Rhythmbox,
+    Tsv,
+    Xspf,
+}
+
+impl Format {
+    /// Names accepted on the command line.
+    pub const NAMES: &'static [&'static str] = &[
+        "clementine",
+        "csv",
+        "folder",
+        "itunes",
+        "m3u",
+        "pls",
+        "rhythmbox",
+        "strawberry",
+        "tsv",
+        "xspf",
+    ];
+
+    /// Guess the format from the file name, assuming iTunes for anything unknown.
+    pub fn detect(path: &str) -> Format {
+        let path = Path::new(path);
+        if path.is_dir() {
+            return Format::Folder;
+        }
+        if path.file_name().is_some_and(|name| name == "playlists.xml") {
+            return Format::Rhythmbox;
+        }
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("csv") => Format::Csv,
+            Some("db") | Some("sqlite") | Some("sqlite3") => Format::Clementine,
+            Some("m3u") | Some("m3u8") => Format::M3u,
+            Some("pls") => Format::Pls,
+            Some("tsv") | Some("tab") => Format::Tsv,
+            Some("xspf") => Format::Xspf,
+            _ => Format::Itunes,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clementine" | "strawberry" => Ok(Format::Clementine),
+            "csv" => Ok(Format::Csv),
+            "folder" => Ok(Format::Folder),
+            "itunes" => Ok(Format::Itunes),
+            "m3u" => Ok(Format::M3u),
+            "pls" => Ok(Format::Pls),
+            "rhythmbox" => Ok(Format::Rhythmbox),
+            "tsv" => Ok(Format::Tsv),
+            "xspf" => Ok(Format::Xspf),
+            _ => Err(Error::new(ErrorKind::Config(format!(
+                "unknown playlist format {}",
+                s
+            )))),
+        }
+    }
+}
+
+/// Options for reading playlists that only apply to some formats.
+#[derive(Debug, Default)]
+pub struct LoadOptions {
+    /// Where to find the fields of a track in a CSV or TSV file.
+    pub column_mappings: Vec<csv::ColumnMapping>,
+    /// Order the tracks of a folder album by album.
+    pub group_by_album: bool,
+}
+
+/// Load a playlist file.
+///
+/// Libraries can be loaded as a playlist only if they hold a single playlist, use `load_library`
+/// for the others.
+pub fn load_playlist(
+    path: &str,
+    format: Format,
+    options: &LoadOptions,
+) -> Result<SourcePlaylist, Error> {
+    match format {
+        Format::Csv => csv::load_playlist(path, b',', &options.column_mappings),
+        Format::Folder => folder::load_playlist(path, options.group_by_album),
+        Format::Itunes => {
+            let playlist = itunes::load_playlist(path)?;
+            Ok(SourcePlaylist {
+                name: match playlist.playlists.as_slice() {
+                    [library_playlist] => Some(library_playlist.name.clone()),
+                    _ => None,
+                },
+                tracks: playlist
+                    .tracks_in_order()
+                    .into_iter()
+                    .map(SourceTrack::from)
+                    .collect(),
+                warnings: Vec::new(),
+            })
+        }
+        Format::M3u => m3u::load_playlist(path),
+        Format::Pls => pls::load_playlist(path),
+        Format::Tsv => csv::load_playlist(path, b'\t', &options.column_mappings),
+        Format::Xspf => xspf::load_playlist(path),
+        Format::Clementine | Format::Rhythmbox => {
+            let mut library = load_library(path, format, options)?;
+            if library.playlists.len() != 1 {
+                return Err(Error::new(ErrorKind::Config(format!(
+                    "{} holds {} playlists, convert them with match-library",
+                    path,
+                    library.playlists.len()
+                ))));
+            }
+            Ok(library.playlists.remove(0))
+        }
+    }
+}
+
+/// Load the named playlists of a library, or a playlist file as a library of one playlist.
+///
+/// iTunes libraries are loaded with `itunes::stream::load_library` instead, which can leave out
+/// tracks that aren't needed and knows about folders and smart playlists.
+pub fn load_library(
+    path: &str,
+    format: Format,
+    options: &LoadOptions,
+) -> Result<SourceLibrary, Error> {
+    match format {
+        Format::Clementine => clementine::load_library(path),
+        Format::Rhythmbox => rhythmbox::load_library(path),
+        _ => {
+            let mut playlist = load_playlist(path, format, options)?;
+            if playlist.name.is_none() {
+                playlist.name = Some(util::file_stem(path));
+            }
+            Ok(SourceLibrary {
+                playlists: vec![playlist],
+                warnings: Vec::new(),
+            })
+        }
+    }
+}
+
+/// The file name without its extension of a `file://` URL, local path or web URL.
+///
+/// Windows paths are split on backslashes too, as playlists written on Windows may be read
+/// anywhere.
+fn location_stem(location: &str) -> Option<String> {
+    let path = decode_location(location)
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|| location.to_string());
+    let file_name = path.rsplit(['/', '\\']).next()?;
+    let stem = match file_name.rfind('.') {
+        Some(i) if i > 0 => &file_name[..i],
+        _ => file_name,
+    };
+    let stem = stem.trim();
+    if stem.is_empty() {
+        None
+    } else {
+        Some(stem.to_string())
+    }
+}
+
+/// The local file of a playlist entry, or `None` for streams and Spotify links. Relative paths
+/// are taken from the folder of the playlist file, `dir`, if it was read from one.
+fn local_path(location: &str, dir: Option<&Path>) -> Option<PathBuf> {
+    if let Some(path) = decode_location(location) {
+        return Some(path);
+    }
+    if location.contains("://") || location.starts_with("spotify:") {
+        return None;
+    }
+    // Windows paths like `C:\Music\...` are absolute wherever the playlist is read.
+    let bytes = location.as_bytes();
+    let windows_absolute = location.starts_with("\\\\")
+        || (bytes.len() >= 3
+            && bytes[0].is_ascii_alphabetic()
+            && bytes[1] == b':'
+            && matches!(bytes[2], b'\\' | b'/'));
+    let path = Path::new(location);
+    match dir {
+        Some(dir) if !path.is_absolute() && !windows_absolute => Some(dir.join(path)),
+        _ => Some(path.to_path_buf()),
+    }
+}
+
+/// The folder of a playlist file, which its relative paths start from. Playlists read from stdin
+/// have none.
+fn playlist_dir(path: &str) -> Option<&Path> {
+    if path == itunes::STDIN_PATH {
+        None
+    } else {
+        Path::new(path).parent()
+    }
+}
+
+/// Set the name of a track from a display name like `Artist - Title`, which players write when
+/// they only have a single title field.
+fn set_display_name(track: &mut SourceTrack, display_name: &str) {
+    match display_name.find(" - ") {
+        Some(i) if track.artist.is_none() => {
+            track.artist = Some(display_name[..i].trim().to_string());
+            track.name = display_name[i + 3..].trim().to_string();
+        }
+        _ => track.name = display_name.trim().to_string(),
+    }
+}
+
+/// Normalize a Spotify track URI or `open.spotify.com` link to a `spotify:track:` URI.
+pub fn normalize_spotify_uri(uri: &str) -> Option<String> {
+    let uri = uri.trim();
+    let id = if let Some(id) = uri.strip_prefix("spotify:track:") {
+        id
+    } else {
+        let path = uri
+            .strip_prefix("https://open.spotify.com/")
+            .or_else(|| uri.strip_prefix("http://open.spotify.com/"))?;
+        // Links may carry a locale, e.g. `intl-de/track/...`, and a query string.
+        let path = path.split(['?', '#']).next().unwrap_or("");
+        let mut segments = path.split('/').skip_while(|s| s.starts_with("intl-"));
+        match (segments.next(), segments.next()) {
+            (Some("track"), Some(id)) => id,
+            _ => return None,
+        }
+    };
+    if !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Some(format!("spotify:track:{}", id))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        local_path, location_stem, normalize_spotify_uri, set_display_name, Format, SourceTrack,
+    };
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(Format::detect("/tmp/Road Trip.csv"), Format::Csv);
+        assert_eq!(Format::detect("export.TSV"), Format::Tsv);
+        assert_eq!(Format::detect("Library.xml"), Format::Itunes);
+        assert_eq!(
+            Format::detect(&std::env::temp_dir().to_string_lossy()),
+            Format::Folder
+        );
+        assert_eq!(
+            Format::detect("/home/me/.local/share/rhythmbox/playlists.xml"),
+            Format::Rhythmbox
+        );
+        assert_eq!(Format::detect("clementine.db"), Format::Clementine);
+        assert_eq!(Format::detect("Favourites.m3u8"), Format::M3u);
+        assert_eq!(Format::detect("-"), Format::Itunes);
+    }
+
+    #[test]
+    fn test_display_name() {
+        let mut track = SourceTrack::default();
+        set_display_name(&mut track, "Daft Punk - Harder, Better, Faster, Stronger");
+        assert_eq!(track.artist.as_deref(), Some("Daft Punk"));
+        assert_eq!(track.name, "Harder, Better, Faster, Stronger");
+
+        let mut track = SourceTrack {
+            artist: Some("Queen".to_string()),
+            ..SourceTrack::default()
+        };
+        set_display_name(&mut track, "Live - Radio Ga Ga");
+        assert_eq!(track.name, "Live - Radio Ga Ga");
+
+        assert_eq!(
+            location_stem("file:///music/01%20Intro.mp3").as_deref(),
+            Some("01 Intro")
+        );
+        assert_eq!(
+            location_stem("http://radio.example.com/live").as_deref(),
+            Some("live")
+        );
+    }
+
+    #[test]
+    fn test_local_path() {
+        let dir = Some(Path::new("/music/playlists"));
+        assert_eq!(
+            local_path("file:///music/01%20Intro.mp3", dir),
+            Some(PathBuf::from("/music/01 Intro.mp3"))
+        );
+        assert_eq!(
+            local_path("../Air/Playground Love.mp3", dir),
+            Some(PathBuf::from("/music/playlists/../Air/Playground Love.mp3"))
+        );
+        assert_eq!(
+            local_path("/music/Air/Playground Love.mp3", dir),
+            Some(PathBuf::from("/music/Air/Playground Love.mp3"))
+        );
+        assert_eq!(
+            local_path("C:\\Music\\One More Time.flac", dir),
+            Some(PathBuf::from("C:\\Music\\One More Time.flac"))
+        );
+        assert_eq!(
+            local_path("01 Intro.mp3", None),
+            Some(PathBuf::from("01 Intro.mp3"))
+        );
+        assert_eq!(local_path("http://radio.example.com/live", dir), None);
+        assert_eq!(
+            local_path("spotify:track:4uLU6hMCjMI75M1A2tKUQC", dir),
+            None
+        );
+    }
+
+    #[test]
+    fn test_normalize_spotify_uri() {
+        let expected = Some("spotify:track:4uLU6hMCjMI75M1A2tKUQC".to_string());
+        assert_eq!(
+            normalize_spotify_uri("spotify:track:4uLU6hMCjMI75M1A2tKUQC"),
+            expected
+        );
+        assert_eq!(
+            normalize_spotify_uri("https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=abc"),
+            expected
+        );
+        assert_eq!(
+            normalize_spotify_uri("https://open.spotify.com/intl-de/track/4uLU6hMCjMI75M1A2tKUQC"),
+            expected
+        );
+        assert_eq!(
+            normalize_spotify_uri("spotify:album:4uLU6hMCjMI75M1A2tKUQC"),
+            None
+        );
+        assert_eq!(normalize_spotify_uri("not a uri"), None);
+    }
+}
@@ -0,0 +1,199 @@
+//! Clementine and Strawberry libraries.
+//!
+//! Both players keep their library and playlists in a SQLite database, e.g.
+//! `~/.config/Clementine/clementine.db` or `~/.local/share/strawberry/strawberry/strawberry.db`.
+//! Playlist items either point at a song of the library or carry their own metadata, e.g. for
+//! streams. Strawberry renamed the library to the collection, and the `filename` column to `url`.
+use super::{location_stem, set_display_name, SourceLibrary, SourcePlaylist, SourceTrack};
+use crate::error::{Error, ErrorKind};
+use crate::itunes::location::decode_location;
+use crate::itunes::STDIN_PATH;
+use crate::util;
+use rusqlite::{Connection, OpenFlags};
+
+pub fn load_library(path: &str) -> Result<SourceLibrary, Error> {
+    if path == STDIN_PATH {
+        return Err(Error::new(ErrorKind::Config(
+            "Clementine and Strawberry databases can't be read from stdin".to_string(),
+        )));
+    }
+    util::check_input_file(path)?;
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| database_error(path, e))?;
+    read_library(&connection).map_err(|e| database_error(path, e))
+}
+
+fn database_error(path: &str, error: rusqlite::Error) -> Error {
+    Error::with_source(
+        ErrorKind::Parse {
+            path: path.to_string(),
+            detail: "could not read Clementine or Strawberry database".to_string(),
+        },
+        error,
+    )
+}
+
+fn columns(connection: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut statement = connection.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = statement.query_map([], |row| row.get::<_, String>(1))?;
+    names.collect()
+}
+
+fn read_library(connection: &Connection) -> rusqlite::Result<SourceLibrary> {
+    let item_columns = columns(connection, "playlist_items")?;
+    let has_column = |name: &str| item_columns.iter().any(|column| column == name);
+    let song_id = if has_column("collection_id") {
+        "collection_id"
+    } else {
+        "library_id"
+    };
+    let location = if has_column("url") { "url" } else { "filename" };
+    // The songs of the library keep their location in a column of the same name.
+    let song_columns = columns(connection, "songs")?;
+    let song_location = if song_columns.iter().any(|column| column == "url") {
+        "url"
+    } else {
+        "filename"
+    };
+
+    let mut playlists = connection.prepare("SELECT ROWID, name FROM playlists ORDER BY ROWID")?;
+    let mut items = connection.prepare(&format!(
+        "SELECT COALESCE(s.title, i.title), COALESCE(s.artist, i.artist),
+                COALESCE(s.album, i.album), COALESCE(s.length, i.length),
+                COALESCE(s.{song_location}, i.{location})
+         FROM playlist_items i LEFT JOIN songs s ON s.ROWID = i.{song_id}
+         WHERE i.playlist = ?1 ORDER BY i.ROWID",
+        location = location,
+        song_location = song_location,
+        song_id = song_id
+    ))?;
+
+    let mut library = SourceLibrary::default();
+    let rows = playlists.query_map([], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?))
+    })?;
+    for row in rows {
+        let (id, name) = row?;
+        let mut playlist = SourcePlaylist {
+            name,
+            ..SourcePlaylist::default()
+        };
+        let tracks = items.query_map([id], |row| {
+            Ok(item_track(
+                non_empty(row.get(0)?),
+                non_empty(row.get(1)?),
+                non_empty(row.get(2)?),
+                row.get(3)?,
+                non_empty(row.get(4)?),
+            ))
+        })?;
+        for track in tracks {
+            if let Some(track) = track? {
+                playlist.tracks.push(track);
+            }
+        }
+        library.playlists.push(playlist);
+    }
+    Ok(library)
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|value| !value.trim().is_empty())
+}
+
+fn item_track(
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    length: Option<i64>,
+    location: Option<String>,
+) -> Option<SourceTrack> {
+    let mut track = SourceTrack {
+        artist,
+        album,
+        // Lengths are stored in nanoseconds, and as -1 when unknown.
+        duration: length.filter(|&ns| ns > 0).map(|ns| ns as u64 / 1_000_000),
+        path: location.as_deref().and_then(decode_location),
+        ..SourceTrack::default()
+    };
+    match title.or_else(|| location.as_deref().and_then(location_stem)) {
+        Some(title) if track.artist.is_some() => track.name = title,
+        Some(title) => set_display_name(&mut track, &title),
+        None => return None,
+    }
+    Some(track)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_library;
+    use rusqlite::Connection;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_clementine_database() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE songs (title TEXT, album TEXT, artist TEXT, length INTEGER, filename TEXT);
+                 CREATE TABLE playlists (name TEXT);
+                 CREATE TABLE playlist_items (playlist INTEGER, type TEXT, library_id INTEGER,
+                     title TEXT, album TEXT, artist TEXT, length INTEGER, filename TEXT);
+                 INSERT INTO songs VALUES ('Teardrop', 'Mezzanine', 'Massive Attack', 330000000000,
+                     'file:///music/Teardrop.flac');
+                 INSERT INTO playlists VALUES ('Trip Hop');
+                 INSERT INTO playlists VALUES ('Empty');
+                 INSERT INTO playlist_items VALUES (1, 'Library', 1, NULL, NULL, NULL, NULL, NULL);
+                 INSERT INTO playlist_items VALUES (1, 'File', NULL, '', '', '', -1,
+                     'file:///music/Portishead%20-%20Glory%20Box.mp3');
+                 INSERT INTO playlist_items VALUES (1, 'Stream', NULL, NULL, NULL, NULL, NULL, NULL);",
+            )
+            .unwrap();
+
+        let library = read_library(&connection).unwrap();
+        assert_eq!(library.playlists.len(), 2);
+        let playlist = &library.playlists[0];
+        assert_eq!(playlist.name.as_deref(), Some("Trip Hop"));
+        assert_eq!(playlist.tracks.len(), 2);
+        assert_eq!(playlist.tracks[0].name, "Teardrop");
+        assert_eq!(playlist.tracks[0].artist.as_deref(), Some("Massive Attack"));
+        assert_eq!(playlist.tracks[0].duration, Some(330_000));
+        assert_eq!(
+            playlist.tracks[0].path,
+            Some(PathBuf::from("/music/Teardrop.flac"))
+        );
+        assert_eq!(playlist.tracks[1].name, "Glory Box");
+        assert_eq!(playlist.tracks[1].artist.as_deref(), Some("Portishead"));
+        assert_eq!(playlist.tracks[1].duration, None);
+        assert_eq!(
+            playlist.tracks[1].path,
+            Some(PathBuf::from("/music/Portishead - Glory Box.mp3"))
+        );
+        assert!(library.playlists[1].tracks.is_empty());
+    }
+
+    #[test]
+    fn test_strawberry_database() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE songs (title TEXT, album TEXT, artist TEXT, length INTEGER, url TEXT);
+                 CREATE TABLE playlists (name TEXT);
+                 CREATE TABLE playlist_items (playlist INTEGER, type INTEGER, collection_id INTEGER,
+                     title TEXT, album TEXT, artist TEXT, length INTEGER, url TEXT);
+                 INSERT INTO songs VALUES ('Angel', 'Mezzanine', 'Massive Attack', 379000000000,
+                     'file:///music/Angel.flac');
+                 INSERT INTO playlists VALUES ('Playlist');
+                 INSERT INTO playlist_items VALUES (1, 2, 1, NULL, NULL, NULL, NULL, NULL);",
+            )
+            .unwrap();
+
+        let library = read_library(&connection).unwrap();
+        assert_eq!(library.playlists[0].tracks[0].name, "Angel");
+        assert_eq!(library.playlists[0].tracks[0].duration, Some(379_000));
+        assert_eq!(
+            library.playlists[0].tracks[0].path,
+            Some(PathBuf::from("/music/Angel.flac"))
+        );
+    }
+}
@@ -0,0 +1,416 @@
+//! Playlists kept in spreadsheets, as CSV or TSV files.
+//!
+//! Columns are found from the header row when there is one, e.g. the `Track Name`,
+//! `Artist Name(s)` and `Track URI` columns of an Exportify export. Columns can also be mapped
+//! explicitly by header or by position, which is needed for files without a header row.
+use super::{normalize_spotify_uri, SourcePlaylist, SourceTrack, SourceWarning};
+use crate::error::{Error, ErrorKind};
+use crate::util;
+use std::str::FromStr;
+
+/// A track attribute that can be read from a column.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Title,
+    Artist,
+    Album,
+    /// Duration in seconds, or as `m:ss` or `h:mm:ss`.
+    Duration,
+    /// Duration in milliseconds.
+    DurationMs,
+    Isrc,
+    SpotifyUri,
+}
+
+impl Field {
+    /// Names accepted on the command line.
+    pub const NAMES: &'static [&'static str] = &[
+        "title",
+        "artist",
+        "album",
+        "duration",
+        "duration-ms",
+        "isrc",
+        "spotify-uri",
+    ];
+
+    const ALL: &'static [Field] = &[
+        Field::Title,
+        Field::Artist,
+        Field::Album,
+        Field::Duration,
+        Field::DurationMs,
+        Field::Isrc,
+        Field::SpotifyUri,
+    ];
+
+    /// Lower case headers recognized for the field when there is no explicit mapping.
+    fn headers(self) -> &'static [&'static str] {
+        match self {
+            Field::Title => &["title", "name", "track", "track name", "song", "song name"],
+            Field::Artist => &[
+                "artist",
+                "artists",
+                "artist(s)",
+                "artist name",
+                "artist name(s)",
+                "performer",
+                "creator",
+            ],
+            Field::Album => &["album", "album name", "album title"],
+            Field::Duration => &["duration", "length", "time"],
+            Field::DurationMs => &["duration (ms)", "duration_ms", "duration ms", "total time"],
+            Field::Isrc => &["isrc"],
+            Field::SpotifyUri => &[
+                "spotify uri",
+                "spotify url",
+                "spotify link",
+                "track uri",
+                "uri",
+            ],
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "title" => Ok(Field::Title),
+            "artist" => Ok(Field::Artist),
+            "album" => Ok(Field::Album),
+            "duration" => Ok(Field::Duration),
+            "duration-ms" => Ok(Field::DurationMs),
+            "isrc" => Ok(Field::Isrc),
+            "spotify-uri" => Ok(Field::SpotifyUri),
+            _ => Err(Error::new(ErrorKind::Config(format!(
+                "unknown column field {}, expected one of {}",
+                s,
+                Field::NAMES.join(", ")
+            )))),
+        }
+    }
+}
+
+/// A column given by its header, or by its position counting from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Column {
+    Header(String),
+    Position(usize),
+}
+
+/// Read a field from a column, given on the command line as `FIELD=COLUMN`, e.g.
+/// `title=Song` or `artist=2`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnMapping {
+    pub field: Field,
+    pub column: Column,
+}
+
+impl FromStr for ColumnMapping {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, column) = match s.find('=') {
+            Some(i) if i + 1 < s.len() => (&s[..i], &s[i + 1..]),
+            _ => {
+                return Err(Error::new(ErrorKind::Config(format!(
+                    "invalid column mapping {}, expected FIELD=COLUMN",
+                    s
+                ))))
+            }
+        };
+        let column = match column.parse::<usize>() {
+            Ok(0) => {
+                return Err(Error::new(ErrorKind::Config(
+                    "column positions start at 1".to_string(),
+                )))
+            }
+            Ok(position) => Column::Position(position),
+            Err(_) => Column::Header(column.to_string()),
+        };
+        Ok(ColumnMapping {
+            field: field.parse()?,
+            column,
+        })
+    }
+}
+
+/// Load a CSV file, or a TSV file if `delimiter` is a tab.
+pub fn load_playlist(
+    path: &str,
+    delimiter: u8,
+    mappings: &[ColumnMapping],
+) -> Result<SourcePlaylist, Error> {
+    let bytes = util::read_input(path)?;
+    parse_playlist(path, &bytes, delimiter, mappings)
+}
+
+fn parse_playlist(
+    path: &str,
+    bytes: &[u8],
+    delimiter: u8,
+    mappings: &[ColumnMapping],
+) -> Result<SourcePlaylist, Error> {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim_start_matches('\u{feff}');
+    let delimiter = sniff_delimiter(text, delimiter);
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let mut records = reader.records();
+    let parse_error = |e: csv::Error| {
+        Error::new(ErrorKind::Parse {
+            path: path.to_string(),
+            detail: e.to_string(),
+        })
+    };
+
+    let first = match records.next() {
+        Some(record) => record.map_err(parse_error)?,
+        None => return Ok(SourcePlaylist::default()),
+    };
+    let headers: Vec<String> = first.iter().map(normalize_header).collect();
+    let has_header = headers.iter().any(|header| {
+        Field::ALL
+            .iter()
+            .any(|f| f.headers().contains(&header.as_str()))
+    }) || mappings.iter().any(|mapping| match mapping.column {
+        Column::Header(ref name) => headers.contains(&normalize_header(name)),
+        Column::Position(_) => false,
+    });
+    let columns = resolve_columns(
+        path,
+        if has_header { Some(&headers) } else { None },
+        mappings,
+    )?;
+
+    let mut playlist = SourcePlaylist::default();
+    if !has_header {
+        read_row(&columns, &first, &mut playlist);
+    }
+    for record in records {
+        read_row(&columns, &record.map_err(parse_error)?, &mut playlist);
+    }
+    Ok(playlist)
+}
+
+/// Excel writes CSV with semicolons in locales that use a decimal comma.
+fn sniff_delimiter(text: &str, delimiter: u8) -> u8 {
+    let first_line = text.lines().next().unwrap_or("");
+    if delimiter == b',' && first_line.matches(';').count() > first_line.matches(',').count() {
+        b';'
+    } else {
+        delimiter
+    }
+}
+
+fn normalize_header(header: &str) -> String {
+    header.trim().to_lowercase()
+}
+
+/// Column indexes of each field, explicit mappings taking precedence over recognized headers.
+fn resolve_columns(
+    path: &str,
+    headers: Option<&Vec<String>>,
+    mappings: &[ColumnMapping],
+) -> Result<Vec<(Field, usize)>, Error> {
+    let mut columns: Vec<(Field, usize)> = Vec::new();
+    match headers {
+        Some(headers) => {
+            for &field in Field::ALL {
+                if let Some(i) = headers
+                    .iter()
+                    .position(|h| field.headers().contains(&h.as_str()))
+                {
+                    columns.push((field, i));
+                }
+            }
+        }
+        None if mappings.is_empty() => {
+            columns = vec![(Field::Title, 0), (Field::Artist, 1), (Field::Album, 2)];
+        }
+        None => (),
+    }
+
+    for mapping in mappings {
+        let index = match mapping.column {
+            Column::Position(position) => position - 1,
+            Column::Header(ref name) => headers
+                .and_then(|headers| headers.iter().position(|h| *h == normalize_header(name)))
+                .ok_or_else(|| {
+                    Error::new(ErrorKind::Config(format!(
+                        "{} has no column named {}",
+                        path, name
+                    )))
+                })?,
+        };
+        columns.retain(|&(field, _)| field != mapping.field);
+        columns.push((mapping.field, index));
+    }
+
+    if !columns.iter().any(|&(field, _)| field == Field::Title) {
+        return Err(Error::new(ErrorKind::Config(format!(
+            "{} has no title column, map one with --column title=COLUMN",
+            path
+        ))));
+    }
+    Ok(columns)
+}
+
+fn read_row(columns: &[(Field, usize)], record: &csv::StringRecord, playlist: &mut SourcePlaylist) {
+    if record.iter().all(|value| value.trim().is_empty()) {
+        return;
+    }
+    let position = format!(
+        "line {}",
+        record.position().map_or(0, |position| position.line())
+    );
+    let mut warn = |message: String| {
+        playlist.warnings.push(SourceWarning {
+            position: position.clone(),
+            message,
+        })
+    };
+
+    let mut track = SourceTrack::default();
+    for &(field, index) in columns {
+        let value = match record.get(index).map(str::trim) {
+            Some(value) if !value.is_empty() => value,
+            _ => continue,
+        };
+        match field {
+            Field::Title => track.name = value.to_string(),
+            Field::Artist => track.artist = Some(value.to_string()),
+            Field::Album => track.album = Some(value.to_string()),
+            Field::Duration => match parse_duration(value) {
+                Some(duration) => track.duration = Some(duration),
+                None => warn(format!("invalid duration {}", value)),
+            },
+            Field::DurationMs => match value.parse::<f64>() {
+                Ok(ms) if ms >= 0.0 => track.duration = Some(ms.round() as u64),
+                _ => warn(format!("invalid duration {}", value)),
+            },
+            Field::Isrc => track.isrc = Some(value.to_uppercase()),
+            Field::SpotifyUri => match normalize_spotify_uri(value) {
+                Some(uri) => track.spotify_uri = Some(uri),
+                None => warn(format!("ignoring invalid Spotify track URI {}", value)),
+            },
+        }
+    }
+
+    if track.name.is_empty() && track.spotify_uri.is_none() {
+        warn("missing title".to_string());
+        return;
+    }
+    playlist.tracks.push(track);
+}
+
+/// Parse a duration in seconds, `m:ss` or `h:mm:ss` into milliseconds.
+fn parse_duration(value: &str) -> Option<u64> {
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        let part: f64 = part.trim().parse().ok()?;
+        if part < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + part;
+    }
+    Some((seconds * 1000.0).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_duration, parse_playlist, Column, ColumnMapping, Field};
+    use crate::error::ErrorKind;
+
+    fn parse(text: &str, delimiter: u8, mappings: &[&str]) -> super::SourcePlaylist {
+        let mappings: Vec<ColumnMapping> = mappings.iter().map(|m| m.parse().unwrap()).collect();
+        parse_playlist("playlist.csv", text.as_bytes(), delimiter, &mappings).unwrap()
+    }
+
+    #[test]
+    fn test_exportify_headers() {
+        let csv = "\u{feff}Track URI,Track Name,Artist URI(s),Artist Name(s),Album Name,Duration (ms),ISRC\n\
+                   spotify:track:4uLU6hMCjMI75M1A2tKUQC,Never Gonna Give You Up,spotify:artist:0gxyHStUsqpMadRV0Di1Qt,Rick Astley,Whenever You Need Somebody,213573,gbarl9300135\n\
+                   ,\"Hello, Goodbye\",,The Beatles,Magical Mystery Tour,,\n";
+        let playlist = parse(csv, b',', &[]);
+        assert_eq!(playlist.tracks.len(), 2);
+        let track = &playlist.tracks[0];
+        assert_eq!(track.name, "Never Gonna Give You Up");
+        assert_eq!(track.artist.as_deref(), Some("Rick Astley"));
+        assert_eq!(track.album.as_deref(), Some("Whenever You Need Somebody"));
+        assert_eq!(track.duration, Some(213_573));
+        assert_eq!(track.isrc.as_deref(), Some("GBARL9300135"));
+        assert_eq!(
+            track.spotify_uri.as_deref(),
+            Some("spotify:track:4uLU6hMCjMI75M1A2tKUQC")
+        );
+        assert_eq!(playlist.tracks[1].name, "Hello, Goodbye");
+        assert_eq!(playlist.tracks[1].spotify_uri, None);
+    }
+
+    #[test]
+    fn test_tsv_without_header_uses_positions() {
+        let playlist = parse(
+            "Yellow\tColdplay\tParachutes\n\nClocks\tColdplay\n",
+            b'\t',
+            &[],
+        );
+        assert_eq!(playlist.tracks.len(), 2);
+        assert_eq!(playlist.tracks[0].album.as_deref(), Some("Parachutes"));
+        assert_eq!(playlist.tracks[1].album, None);
+    }
+
+    #[test]
+    fn test_explicit_mapping() {
+        let csv = "Song;Band;Length\nYellow;Coldplay;4:29\n;Coldplay;1:00\n";
+        let playlist = parse(csv, b',', &["title=Song", "artist=2", "duration=Length"]);
+        assert_eq!(playlist.tracks.len(), 1);
+        assert_eq!(playlist.tracks[0].artist.as_deref(), Some("Coldplay"));
+        assert_eq!(playlist.tracks[0].duration, Some(269_000));
+        assert_eq!(playlist.warnings.len(), 1);
+        assert_eq!(playlist.warnings[0].to_string(), "line 3: missing title");
+    }
+
+    #[test]
+    fn test_missing_title_column() {
+        let error = parse_playlist(
+            "playlist.csv",
+            b"Band\nColdplay\n",
+            b',',
+            &["artist=Band".parse().unwrap()],
+        )
+        .unwrap_err();
+        match error.kind {
+            ErrorKind::Config(_) => (),
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_parse_column_mapping() {
+        assert_eq!(
+            "isrc=3".parse::<ColumnMapping>().unwrap(),
+            ColumnMapping {
+                field: Field::Isrc,
+                column: Column::Position(3)
+            }
+        );
+        assert!("isrc=0".parse::<ColumnMapping>().is_err());
+        assert!("genre=Genre".parse::<ColumnMapping>().is_err());
+        assert!("title".parse::<ColumnMapping>().is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("215"), Some(215_000));
+        assert_eq!(parse_duration("3:35"), Some(215_000));
+        assert_eq!(parse_duration("1:02:03.5"), Some(3_723_500));
+        assert_eq!(parse_duration("soon"), None);
+    }
+}
@@ -0,0 +1,319 @@
+//! Folders of audio files, e.g. ripped CDs that never lived in a player's library.
+//!
+//! Tracks are read from the tags of the files. Anything the tags leave out is taken from the path,
+//! assuming the common `Artist/Album/NN Title.ext` layout.
+use super::{set_display_name, SourcePlaylist, SourceTrack, SourceWarning};
+use crate::error::{Error, ErrorKind};
+use lofty::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Extensions of the audio files read from a folder, everything else is ignored.
+pub const AUDIO_EXTENSIONS: &[&str] = &[
+    "aac", "aif", "aiff", "ape", "flac", "m4a", "mp3", "mp4", "mpc", "oga", "ogg", "opus", "spx",
+    "wav", "wma", "wv",
+];
+
+/// Load the audio files below `path` as a playlist named after the folder.
+///
+/// Files are in path order, unless `group_by_album` is set: then the albums are in the order they
+/// are first found, each with its tracks in disc and track number order.
+pub fn load_playlist(path: &str, group_by_album: bool) -> Result<SourcePlaylist, Error> {
+    let root = Path::new(path);
+    if !root.is_dir() {
+        return Err(Error::new(ErrorKind::FileNotFound(format!(
+            "Can't find folder {}",
+            path
+        ))));
+    }
+
+    let mut playlist = SourcePlaylist {
+        name: root
+            .canonicalize()
+            .ok()
+            .and_then(|root| root.file_name().map(|n| n.to_string_lossy().into_owned())),
+        ..SourcePlaylist::default()
+    };
+    let mut tracks = Vec::new();
+    let entries = WalkDir::new(root)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter();
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                playlist.warnings.push(SourceWarning {
+                    position: e
+                        .path()
+                        .map(|path| path.display().to_string())
+                        .unwrap_or_else(|| path.to_string()),
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        if entry.file_type().is_file() && is_audio_file(entry.path()) {
+            tracks.push(read_track(root, entry.path()));
+        }
+    }
+
+    if group_by_album {
+        tracks = group_tracks_by_album(tracks);
+    }
+    playlist.tracks = tracks;
+    Ok(playlist)
+}
+
+fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .is_some_and(|extension| AUDIO_EXTENSIONS.contains(&extension.as_str()))
+}
+
+/// Read a track from the tags of a file, filling the gaps from its path.
+fn read_track(root: &Path, path: &Path) -> SourceTrack {
+    let mut track = SourceTrack::default();
+    match lofty::read_from_path(path) {
+        Ok(file) => {
+            if let Some(tag) = file.primary_tag().or_else(|| file.first_tag()) {
+                let text = |value: Option<std::borrow::Cow<str>>| {
+                    value
+                        .map(|value| value.trim().to_string())
+                        .filter(|value| !value.is_empty())
+                };
+                track.name = text(tag.title()).unwrap_or_default();
+                track.artist = text(tag.artist());
+                track.album = text(tag.album());
+                track.isrc = text(tag.get_string(ItemKey::Isrc).map(Into::into));
+                track.album_artist = text(tag.get_string(ItemKey::AlbumArtist).map(Into::into));
+                track.disc_number = tag.disk();
+                track.track_number = tag.track();
+            }
+            let duration = file.properties().duration().as_millis() as u64;
+            track.duration = Some(duration).filter(|&duration| duration > 0);
+        }
+        Err(e) => debug!("no tags read from {}: {}", path.display(), e),
+    }
+
+    let from_path = parse_path(root, path);
+    if track.name.is_empty() {
+        track.name = from_path.name;
+    }
+    if track.artist.is_none() {
+        track.artist = from_path.artist;
+    }
+    if track.album.is_none() {
+        track.album = from_path.album;
+    }
+    if track.album_artist.is_none() {
+        track.album_artist = from_path.album_artist;
+    }
+    track.disc_number = track.disc_number.or(from_path.disc_number);
+    track.track_number = track.track_number.or(from_path.track_number);
+    track.path = Some(path.to_path_buf());
+    track
+}
+
+/// Read a track from a path like `Artist/Album/NN Title.ext` below `root`.
+///
+/// The file name may also have the form `Artist - Title`, or start with a disc number, e.g.
+/// `1-02 Title`. Discs may also be in folders of their own, e.g. `Album/CD1/01 Title.ext`. With a
+/// single folder below the root it is taken to be the album.
+fn parse_path(root: &Path, path: &Path) -> SourceTrack {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let folders: Vec<String> = relative
+        .parent()
+        .map(|parent| {
+            parent
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut track = SourceTrack::default();
+    let mut folders = folders.as_slice();
+    if let Some((last, parents)) = folders.split_last() {
+        if let Some(disc) = disc_folder_number(last) {
+            track.disc_number = Some(disc);
+            folders = parents;
+        }
+    }
+    match folders {
+        [.., artist, album] => {
+            track.artist = Some(artist.clone());
+            track.album_artist = Some(artist.clone());
+            track.album = Some(album.clone());
+        }
+        [album] => track.album = Some(album.clone()),
+        [] => (),
+    }
+
+    let (disc, number, title) = split_track_number(&stem);
+    track.disc_number = disc.or(track.disc_number);
+    track.track_number = number;
+    // A file name like `Artist - Title` names the artist of this track, which may not be the
+    // artist of the album folder it is in, e.g. on compilations.
+    if title.contains(" - ") {
+        track.artist = None;
+    }
+    set_display_name(&mut track, title);
+    if track.artist.is_none() {
+        track.artist = track.album_artist.clone();
+    }
+    track
+}
+
+/// The number of a folder like `CD1` or `Disc 2`, which holds one disc of an album.
+fn disc_folder_number(folder: &str) -> Option<u32> {
+    let lowercase = folder.to_lowercase();
+    let number = ["cd", "disc", "disk"]
+        .iter()
+        .find_map(|prefix| lowercase.strip_prefix(prefix))?;
+    number.trim_start_matches([' ', '_', '-']).parse().ok()
+}
+
+/// Split a leading `NN` or `D-NN` track number off a file name.
+fn split_track_number(stem: &str) -> (Option<u32>, Option<u32>, &str) {
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let end = digits(stem);
+    if end == 0 {
+        return (None, None, stem);
+    }
+    let (mut disc, mut number) = (None, stem[..end].parse().ok());
+    let mut rest = &stem[end..];
+    if let Some(after_dash) = rest.strip_prefix('-') {
+        let end = digits(after_dash);
+        if end > 0 {
+            disc = number;
+            number = after_dash[..end].parse().ok();
+            rest = &after_dash[end..];
+        }
+    }
+    let title = rest.trim_start_matches([' ', '.', '-', '_']).trim();
+    // A name that is only a number, or a number not followed by a separator like `1999.mp3` or
+    // `99 Luftballons` without the space, is the title itself.
+    if title.is_empty() || title.len() == rest.len() {
+        (None, None, stem)
+    } else {
+        (disc, number, title)
+    }
+}
+
+/// Order tracks album by album, keeping the albums in the order they were first found.
+fn group_tracks_by_album(tracks: Vec<SourceTrack>) -> Vec<SourceTrack> {
+    let mut albums: Vec<Vec<SourceTrack>> = Vec::new();
+    let mut album_indices: HashMap<(Option<String>, Option<String>), usize> = HashMap::new();
+    for track in tracks {
+        let key = (
+            track
+                .album_artist
+                .as_ref()
+                .or(track.artist.as_ref())
+                .map(|artist| artist.to_lowercase()),
+            track.album.as_ref().map(|album| album.to_lowercase()),
+        );
+        let index = *album_indices.entry(key).or_insert_with(|| {
+            albums.push(Vec::new());
+            albums.len() - 1
+        });
+        albums[index].push(track);
+    }
+    for album in &mut albums {
+        // The sort is stable, so tracks without numbers stay in path order at the end.
+        album.sort_by_key(|track| {
+            (
+                track.disc_number.unwrap_or(1),
+                track.track_number.is_none(),
+                track.track_number,
+            )
+        });
+    }
+    albums.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disc_folder_number, group_tracks_by_album, parse_path, split_track_number};
+    use std::path::Path;
+
+    #[test]
+    fn test_split_track_number() {
+        assert_eq!(split_track_number("01 Airbag"), (None, Some(1), "Airbag"));
+        assert_eq!(
+            split_track_number("2-03. Karma Police"),
+            (Some(2), Some(3), "Karma Police")
+        );
+        assert_eq!(
+            split_track_number("07 - Radiohead - Lucky"),
+            (None, Some(7), "Radiohead - Lucky")
+        );
+        assert_eq!(split_track_number("1999"), (None, None, "1999"));
+        assert_eq!(
+            split_track_number("99Luftballons"),
+            (None, None, "99Luftballons")
+        );
+        assert_eq!(split_track_number("Let Down"), (None, None, "Let Down"));
+    }
+
+    #[test]
+    fn test_parse_path() {
+        let root = Path::new("/music");
+        let track = parse_path(
+            root,
+            Path::new("/music/Radiohead/OK Computer/02 Paranoid Android.flac"),
+        );
+        assert_eq!(track.name, "Paranoid Android");
+        assert_eq!(track.artist.as_deref(), Some("Radiohead"));
+        assert_eq!(track.album.as_deref(), Some("OK Computer"));
+        assert_eq!(track.track_number, Some(2));
+
+        let track = parse_path(
+            root,
+            Path::new("/music/Various/Trainspotting/03 Iggy Pop - Lust for Life.mp3"),
+        );
+        assert_eq!(track.name, "Lust for Life");
+        assert_eq!(track.artist.as_deref(), Some("Iggy Pop"));
+        assert_eq!(track.album_artist.as_deref(), Some("Various"));
+
+        let track = parse_path(root, Path::new("/music/Air - Playground Love.mp3"));
+        assert_eq!(track.name, "Playground Love");
+        assert_eq!(track.artist.as_deref(), Some("Air"));
+        assert_eq!(track.album, None);
+    }
+
+    #[test]
+    fn test_group_tracks_by_album() {
+        let root = Path::new("/music");
+        let tracks = [
+            "/music/A/First/02 Two.mp3",
+            "/music/B/Second/01 One.mp3",
+            "/music/A/First/CD1/01 One.mp3",
+            "/music/A/First/Bonus.mp3",
+        ]
+        .iter()
+        .map(|path| parse_path(root, Path::new(path)))
+        .collect();
+        let names: Vec<String> = group_tracks_by_album(tracks)
+            .into_iter()
+            .map(|track| format!("{} {}", track.album.unwrap(), track.name))
+            .collect();
+        assert_eq!(
+            names,
+            vec!["First One", "First Two", "First Bonus", "Second One"]
+        );
+    }
+
+    #[test]
+    fn test_disc_folder_number() {
+        assert_eq!(disc_folder_number("CD1"), Some(1));
+        assert_eq!(disc_folder_number("Disc 2"), Some(2));
+        assert_eq!(disc_folder_number("Discovery"), None);
+    }
+}
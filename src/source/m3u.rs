@@ -0,0 +1,122 @@
+//! M3U and M3U8 playlists.
+//!
+//! Most players can write these, so they are also the way in for players whose own formats can't
+//! be read, e.g. foobar2000's binary `.fpl` playlists exported with "Save playlist" as M3U.
+use super::{
+    local_path, location_stem, normalize_spotify_uri, playlist_dir, set_display_name,
+    SourcePlaylist, SourceTrack,
+};
+use crate::error::Error;
+use crate::util;
+use std::path::Path;
+
+pub fn load_playlist(path: &str) -> Result<SourcePlaylist, Error> {
+    let bytes = util::read_input(path)?;
+    let mut playlist = parse_playlist(&bytes, playlist_dir(path));
+    if playlist.name.is_none() && path != crate::itunes::STDIN_PATH {
+        playlist.name = Some(util::file_stem(path));
+    }
+    Ok(playlist)
+}
+
+fn parse_playlist(bytes: &[u8], dir: Option<&Path>) -> SourcePlaylist {
+    let text = String::from_utf8_lossy(bytes);
+    let mut playlist = SourcePlaylist::default();
+    // Extended M3U describes an entry on the lines before its location.
+    let mut next = SourceTrack::default();
+    let mut display_name: Option<String> = None;
+
+    for line in text.lines() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+            playlist.name = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+        } else if let Some(info) = line.strip_prefix("#EXTINF:") {
+            // `#EXTINF:<seconds> [attributes],<display name>`
+            let (head, name) = match info.find(',') {
+                Some(i) => (&info[..i], info[i + 1..].trim()),
+                None => (info, ""),
+            };
+            let seconds = head
+                .split_whitespace()
+                .next()
+                .and_then(|s| s.parse::<i64>().ok());
+            next.duration = seconds.filter(|&s| s >= 0).map(|s| s as u64 * 1000);
+            display_name = Some(name.to_string()).filter(|name| !name.is_empty());
+        } else if let Some(artist) = line.strip_prefix("#EXTART:") {
+            next.artist = Some(artist.trim().to_string());
+        } else if let Some(album) = line.strip_prefix("#EXTALB:") {
+            next.album = Some(album.trim().to_string());
+        } else if line.starts_with('#') {
+            continue;
+        } else {
+            let mut track = std::mem::take(&mut next);
+            track.spotify_uri = normalize_spotify_uri(line);
+            track.path = local_path(line, dir);
+            match display_name.take().or_else(|| location_stem(line)) {
+                Some(name) => set_display_name(&mut track, &name),
+                None => track.name = line.to_string(),
+            }
+            playlist.tracks.push(track);
+        }
+    }
+    playlist
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_playlist;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_extended_m3u() {
+        let m3u = "#EXTM3U\n\
+                   #PLAYLIST:Road Trip\n\
+                   #EXTINF:354,Queen - Bohemian Rhapsody\n\
+                   #EXTALB:A Night at the Opera\n\
+                   /music/Queen/Bohemian Rhapsody.mp3\n\
+                   \n\
+                   #EXTINF:-1 tvg-name=\"Radio\",Radio Paradise\n\
+                   http://stream.radioparadise.com/mp3-128\n\
+                   C:\\Music\\Daft Punk - One More Time.flac\n";
+        let playlist = parse_playlist(m3u.as_bytes(), Some(Path::new("/music/playlists")));
+        assert_eq!(playlist.name.as_deref(), Some("Road Trip"));
+        assert_eq!(playlist.tracks.len(), 3);
+        let track = &playlist.tracks[0];
+        assert_eq!(track.name, "Bohemian Rhapsody");
+        assert_eq!(track.artist.as_deref(), Some("Queen"));
+        assert_eq!(track.album.as_deref(), Some("A Night at the Opera"));
+        assert_eq!(track.duration, Some(354_000));
+        assert_eq!(
+            track.path,
+            Some(PathBuf::from("/music/Queen/Bohemian Rhapsody.mp3"))
+        );
+        assert_eq!(playlist.tracks[1].name, "Radio Paradise");
+        assert_eq!(playlist.tracks[1].duration, None);
+        assert_eq!(playlist.tracks[1].path, None);
+        assert_eq!(playlist.tracks[2].name, "One More Time");
+        assert_eq!(playlist.tracks[2].artist.as_deref(), Some("Daft Punk"));
+    }
+
+    #[test]
+    fn test_plain_m3u() {
+        let playlist = parse_playlist(
+            b"01 Intro.mp3\r\nfile:///music/Air%20-%20Playground%20Love.mp3\r\n",
+            Some(Path::new("/music/Air")),
+        );
+        let names: Vec<&str> = playlist.tracks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["01 Intro", "Playground Love"]);
+        assert_eq!(
+            playlist.tracks[0].path,
+            Some(PathBuf::from("/music/Air/01 Intro.mp3"))
+        );
+        assert_eq!(
+            playlist.tracks[1].path,
+            Some(PathBuf::from("/music/Air - Playground Love.mp3"))
+        );
+        assert_eq!(playlist.tracks[1].artist.as_deref(), Some("Air"));
+        assert_eq!(playlist.name, None);
+    }
+}
@@ -0,0 +1,127 @@
+//! PLS playlists, as written by Winamp-family players and web radio tools.
+//!
+//! Entries are spread over numbered keys, e.g. `File1`, `Title1` and `Length1`, where the title
+//! usually has the form `Artist - Title` and the length is in seconds, or `-1` for streams.
+use super::{
+    local_path, location_stem, normalize_spotify_uri, playlist_dir, set_display_name,
+    SourcePlaylist, SourceTrack, SourceWarning,
+};
+use crate::error::Error;
+use crate::util;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub fn load_playlist(path: &str) -> Result<SourcePlaylist, Error> {
+    let bytes = util::read_input(path)?;
+    Ok(parse_playlist(&bytes, playlist_dir(path)))
+}
+
+#[derive(Default)]
+struct Entry {
+    file: Option<String>,
+    title: Option<String>,
+    length: Option<String>,
+}
+
+fn parse_playlist(bytes: &[u8], dir: Option<&Path>) -> SourcePlaylist {
+    let text = String::from_utf8_lossy(bytes);
+    let mut entries: BTreeMap<u32, Entry> = BTreeMap::new();
+    for line in text.lines() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        let (key, value) = match line.find('=') {
+            Some(i) => (line[..i].trim().to_lowercase(), line[i + 1..].trim()),
+            None => continue,
+        };
+        // Keys are case-insensitive and numbered from 1, e.g. `File1`.
+        let split = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+        let number: u32 = match key[split..].parse() {
+            Ok(number) => number,
+            Err(_) => continue,
+        };
+        let entry = entries.entry(number).or_default();
+        let value = Some(value.to_string()).filter(|value| !value.is_empty());
+        match &key[..split] {
+            "file" => entry.file = value,
+            "title" => entry.title = value,
+            "length" => entry.length = value,
+            _ => (),
+        }
+    }
+
+    let mut playlist = SourcePlaylist::default();
+    for (number, entry) in entries {
+        let mut track = SourceTrack::default();
+        if let Some(ref file) = entry.file {
+            track.spotify_uri = normalize_spotify_uri(file);
+            track.path = local_path(file, dir);
+        }
+        match entry.title {
+            Some(ref title) => set_display_name(&mut track, title),
+            None => {
+                if let Some(stem) = entry.file.as_deref().and_then(location_stem) {
+                    set_display_name(&mut track, &stem);
+                }
+            }
+        }
+        if let Some(ref length) = entry.length {
+            match length.parse::<i64>() {
+                Ok(seconds) if seconds >= 0 => track.duration = Some(seconds as u64 * 1000),
+                Ok(_) => (),
+                Err(_) => playlist.warnings.push(SourceWarning {
+                    position: format!("entry {}", number),
+                    message: format!("invalid length {}", length),
+                }),
+            }
+        }
+
+        if track.name.is_empty() && track.spotify_uri.is_none() {
+            playlist.warnings.push(SourceWarning {
+                position: format!("entry {}", number),
+                message: "missing title".to_string(),
+            });
+        } else {
+            playlist.tracks.push(track);
+        }
+    }
+    playlist
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_playlist;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_parse_pls() {
+        let pls = "[playlist]\r\n\
+                   File1=/music/Daft Punk/Discovery/01 One More Time.mp3\r\n\
+                   Title1=Daft Punk - One More Time\r\n\
+                   Length1=320\r\n\
+                   file2=http://radio.example.com/live\r\n\
+                   length2=-1\r\n\
+                   File10=file:///music/Air%20-%20La%20Femme%20d'Argent.flac\r\n\
+                   Title3=\r\n\
+                   NumberOfEntries=3\r\n\
+                   Version=2\r\n";
+        let playlist = parse_playlist(pls.as_bytes(), Some(Path::new("/radio")));
+        let names: Vec<&str> = playlist.tracks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["One More Time", "live", "La Femme d'Argent"]);
+        assert_eq!(playlist.tracks[0].artist.as_deref(), Some("Daft Punk"));
+        assert_eq!(playlist.tracks[0].duration, Some(320_000));
+        assert_eq!(playlist.tracks[1].duration, None);
+        assert_eq!(
+            playlist.tracks[0].path,
+            Some(PathBuf::from(
+                "/music/Daft Punk/Discovery/01 One More Time.mp3"
+            ))
+        );
+        assert_eq!(playlist.tracks[1].path, None);
+        assert_eq!(playlist.tracks[2].artist.as_deref(), Some("Air"));
+        assert_eq!(
+            playlist.tracks[2].path,
+            Some(PathBuf::from("/music/Air - La Femme d'Argent.flac"))
+        );
+        assert_eq!(playlist.warnings.len(), 1);
+        assert_eq!(playlist.warnings[0].to_string(), "entry 3: missing title");
+    }
+}
@@ -0,0 +1,239 @@
+//! Rhythmbox libraries.
+//!
+//! Rhythmbox keeps its playlists in `playlists.xml`, where static playlists list their tracks by
+//! location. The track metadata is in `rhythmdb.xml` next to it, usually in
+//! `~/.local/share/rhythmbox/`. Automatic playlists are saved searches and can't be converted.
+use super::{
+    location_stem, set_display_name, SourceLibrary, SourcePlaylist, SourceTrack, SourceWarning,
+};
+use crate::error::{Error, ErrorKind};
+use crate::itunes::location::decode_location;
+use crate::util;
+use quick_xml::escape::unescape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::name::QName;
+use quick_xml::{Reader, XmlVersion};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Name of the track database Rhythmbox keeps next to `playlists.xml`.
+const RHYTHMDB_FILE_NAME: &str = "rhythmdb.xml";
+
+/// Load the playlists of a Rhythmbox library from its `playlists.xml`.
+pub fn load_library(path: &str) -> Result<SourceLibrary, Error> {
+    let database_path = Path::new(path).with_file_name(RHYTHMDB_FILE_NAME);
+    let database = if database_path.is_file() {
+        let database_path = database_path.to_string_lossy();
+        parse_database(&database_path, &util::read_input(&database_path)?)?
+    } else {
+        warn!(
+            "{} not found, track names are taken from file names",
+            database_path.display()
+        );
+        HashMap::new()
+    };
+    parse_playlists(path, &util::read_input(path)?, &database)
+}
+
+fn parse_error(path: &str, reader: &Reader<&[u8]>, detail: String) -> Error {
+    Error::new(ErrorKind::Parse {
+        path: path.to_string(),
+        detail: format!("{} (offset {})", detail, reader.buffer_position()),
+    })
+}
+
+/// Read the text of the element that just started, skipping any elements nested in it.
+fn read_text(
+    path: &str,
+    reader: &mut Reader<&[u8]>,
+    element: &BytesStart,
+) -> Result<String, Error> {
+    let end = element.name().as_ref().to_string();
+    let raw = reader
+        .read_text(QName(&end))
+        .map_err(|e| parse_error(path, reader, e.to_string()))?;
+    let text = unescape(&raw).map_err(|e| parse_error(path, reader, e.to_string()))?;
+    Ok(text.trim().to_string())
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    let attribute = element.try_get_attribute(name).ok()??;
+    attribute
+        .normalized_value(XmlVersion::default())
+        .ok()
+        .map(|value| value.into_owned())
+}
+
+/// Songs of `rhythmdb.xml` by location.
+fn parse_database(path: &str, bytes: &[u8]) -> Result<HashMap<String, SourceTrack>, Error> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut reader = Reader::from_str(&text);
+    let mut tracks = HashMap::new();
+    let mut entry: Option<(SourceTrack, Option<String>)> = None;
+
+    loop {
+        match reader
+            .read_event()
+            .map_err(|e| parse_error(path, &reader, e.to_string()))?
+        {
+            // Radio stations, podcast feeds and ignored files are entries too.
+            Event::Start(ref element)
+                if element.local_name().as_ref() == "entry"
+                    && attribute(element, "type").as_deref() == Some("song") =>
+            {
+                entry = Some((SourceTrack::default(), None));
+            }
+            Event::Start(ref element) if entry.is_some() => {
+                let field = element.local_name().as_ref().to_string();
+                let value = read_text(path, &mut reader, element)?;
+                let (track, location) = entry.as_mut().unwrap();
+                if value.is_empty() {
+                    continue;
+                }
+                match field.as_str() {
+                    "title" => track.name = value,
+                    "artist" => track.artist = Some(value),
+                    "album" => track.album = Some(value),
+                    "album-artist" => track.album_artist = Some(value),
+                    "disc-number" => track.disc_number = value.parse().ok(),
+                    "track-number" => track.track_number = value.parse().ok(),
+                    "duration" => track.duration = value.parse::<u64>().ok().map(|s| s * 1000),
+                    "location" => *location = Some(value),
+                    _ => (),
+                }
+            }
+            Event::End(ref element) if element.local_name().as_ref() == "entry" => {
+                if let Some((track, Some(location))) = entry.take() {
+                    tracks.insert(location, track);
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    Ok(tracks)
+}
+
+fn parse_playlists(
+    path: &str,
+    bytes: &[u8],
+    database: &HashMap<String, SourceTrack>,
+) -> Result<SourceLibrary, Error> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut reader = Reader::from_str(&text);
+    let mut library = SourceLibrary::default();
+    let mut playlist: Option<SourcePlaylist> = None;
+
+    loop {
+        match reader
+            .read_event()
+            .map_err(|e| parse_error(path, &reader, e.to_string()))?
+        {
+            Event::Start(ref element) if element.local_name().as_ref() == "playlist" => {
+                let name = attribute(element, "name").unwrap_or_default();
+                let skip = match attribute(element, "type").as_deref() {
+                    Some("automatic") => {
+                        library.warnings.push(SourceWarning {
+                            position: format!("playlist {}", name),
+                            message: "automatic playlists can't be converted".to_string(),
+                        });
+                        true
+                    }
+                    // The play queue is a playlist too, but not one anybody would want to convert.
+                    Some("queue") => true,
+                    _ => false,
+                };
+                if skip {
+                    let end = element.name().as_ref().to_string();
+                    reader
+                        .read_to_end(QName(&end))
+                        .map_err(|e| parse_error(path, &reader, e.to_string()))?;
+                    continue;
+                }
+                playlist = Some(SourcePlaylist {
+                    name: Some(name),
+                    ..SourcePlaylist::default()
+                });
+            }
+            Event::Start(ref element)
+                if playlist.is_some() && element.local_name().as_ref() == "location" =>
+            {
+                let location = read_text(path, &mut reader, element)?;
+                let mut track = match database.get(&location) {
+                    Some(track) if !track.name.is_empty() => track.clone(),
+                    _ => {
+                        let mut track = SourceTrack::default();
+                        match location_stem(&location) {
+                            Some(stem) => set_display_name(&mut track, &stem),
+                            None => continue,
+                        }
+                        track
+                    }
+                };
+                track.path = decode_location(&location);
+                playlist.as_mut().unwrap().tracks.push(track);
+            }
+            Event::End(ref element) if element.local_name().as_ref() == "playlist" => {
+                if let Some(playlist) = playlist.take() {
+                    library.playlists.push(playlist);
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    Ok(library)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_database, parse_playlists};
+
+    #[test]
+    fn test_rhythmbox_library() {
+        let database = r#"<?xml version="1.0" standalone="yes"?>
+<rhythmdb version="2.0">
+  <entry type="song">
+    <title>Paranoid Android</title>
+    <genre>Alternative</genre>
+    <artist>Radiohead</artist>
+    <album>OK Computer</album>
+    <duration>387</duration>
+    <location>file:///home/me/Music/Radiohead/02%20Paranoid%20Android.mp3</location>
+  </entry>
+  <entry type="iradio">
+    <title>Radio Paradise</title>
+    <location>http://stream.radioparadise.com/mp3-128</location>
+  </entry>
+</rhythmdb>"#;
+        let playlists = r#"<?xml version="1.0"?>
+<rhythmdb-playlists>
+  <playlist name="My top rated" show-browser="false" browser-position="180" search-type="search-match" type="automatic">
+    <conjunction><equals prop="rating">5</equals></conjunction>
+  </playlist>
+  <playlist name="Favourites &amp; more" show-browser="false" browser-position="180" search-type="search-match" type="static">
+    <location>file:///home/me/Music/Radiohead/02%20Paranoid%20Android.mp3</location>
+    <location>file:///home/me/Music/Portishead%20-%20Roads.mp3</location>
+  </playlist>
+  <playlist name="Play Queue" show-browser="false" browser-position="180" search-type="search-match" type="queue">
+    <location>file:///home/me/Music/Portishead%20-%20Roads.mp3</location>
+  </playlist>
+</rhythmdb-playlists>"#;
+
+        let database = parse_database("rhythmdb.xml", database.as_bytes()).unwrap();
+        assert_eq!(database.len(), 1);
+        let library = parse_playlists("playlists.xml", playlists.as_bytes(), &database).unwrap();
+        assert_eq!(library.playlists.len(), 1);
+        let playlist = &library.playlists[0];
+        assert_eq!(playlist.name.as_deref(), Some("Favourites & more"));
+        assert_eq!(playlist.tracks[0].name, "Paranoid Android");
+        assert_eq!(playlist.tracks[0].album.as_deref(), Some("OK Computer"));
+        assert_eq!(playlist.tracks[0].duration, Some(387_000));
+        assert_eq!(playlist.tracks[1].name, "Roads");
+        assert_eq!(playlist.tracks[1].artist.as_deref(), Some("Portishead"));
+        assert_eq!(
+            library.warnings[0].to_string(),
+            "playlist My top rated: automatic playlists can't be converted"
+        );
+    }
+}
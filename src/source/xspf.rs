@@ -0,0 +1,181 @@
+//! XSPF playlists, as written by VLC and many web players.
+use super::{
+    local_path, location_stem, normalize_spotify_uri, playlist_dir, set_display_name,
+    SourcePlaylist, SourceTrack, SourceWarning,
+};
+use crate::error::{Error, ErrorKind};
+use crate::util;
+use quick_xml::escape::unescape;
+use quick_xml::events::Event;
+use quick_xml::name::QName;
+use quick_xml::Reader;
+
+pub fn load_playlist(path: &str) -> Result<SourcePlaylist, Error> {
+    let bytes = util::read_input(path)?;
+    parse_playlist(path, &bytes)
+}
+
+fn parse_playlist(path: &str, bytes: &[u8]) -> Result<SourcePlaylist, Error> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut reader = Reader::from_str(&text);
+    let parse_error = |reader: &Reader<&[u8]>, detail: String| {
+        Error::new(ErrorKind::Parse {
+            path: path.to_string(),
+            detail: format!("{} (offset {})", detail, reader.buffer_position()),
+        })
+    };
+
+    let mut playlist = SourcePlaylist::default();
+    let mut track: Option<(SourceTrack, Option<String>)> = None;
+    let mut number = 0;
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| parse_error(&reader, e.to_string()))?;
+        match event {
+            Event::Start(ref element) if element.local_name().as_ref() == "track" => {
+                number += 1;
+                track = Some((SourceTrack::default(), None));
+            }
+            // The title of the playlist comes before its track list.
+            Event::Start(ref element)
+                if track.is_none()
+                    && element.local_name().as_ref() == "title"
+                    && playlist.name.is_none() =>
+            {
+                let end = element.name().as_ref().to_string();
+                let raw = reader
+                    .read_text(QName(&end))
+                    .map_err(|e| parse_error(&reader, e.to_string()))?;
+                let title = unescape(&raw).map_err(|e| parse_error(&reader, e.to_string()))?;
+                playlist.name = Some(title.trim().to_string()).filter(|title| !title.is_empty());
+            }
+            Event::Start(ref element) if track.is_some() => {
+                let name = element.local_name().as_ref().to_string();
+                let end = element.name().as_ref().to_string();
+                let raw = reader
+                    .read_text(QName(&end))
+                    .map_err(|e| parse_error(&reader, e.to_string()))?;
+                let value = unescape(&raw)
+                    .map_err(|e| parse_error(&reader, e.to_string()))?
+                    .trim()
+                    .to_string();
+                if value.is_empty() {
+                    continue;
+                }
+                let (track, location) = track.as_mut().unwrap();
+                match name.as_str() {
+                    "title" => track.name = value,
+                    "creator" => track.artist = Some(value),
+                    "album" => track.album = Some(value),
+                    "duration" => match value.parse() {
+                        Ok(duration) => track.duration = Some(duration),
+                        Err(_) => playlist.warnings.push(SourceWarning {
+                            position: format!("track {}", number),
+                            message: format!("invalid duration {}", value),
+                        }),
+                    },
+                    "location" | "identifier" => {
+                        if let Some(uri) = normalize_spotify_uri(&value) {
+                            track.spotify_uri = Some(uri);
+                        } else if location.is_none() && name == "location" {
+                            *location = Some(value);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            Event::End(ref element) if element.local_name().as_ref() == "track" => {
+                if let Some((mut source_track, location)) = track.take() {
+                    source_track.path = location
+                        .as_deref()
+                        .and_then(|location| local_path(location, playlist_dir(path)));
+                    if source_track.name.is_empty() {
+                        if let Some(stem) = location.as_deref().and_then(location_stem) {
+                            set_display_name(&mut source_track, &stem);
+                        }
+                    }
+                    if source_track.name.is_empty() && source_track.spotify_uri.is_none() {
+                        playlist.warnings.push(SourceWarning {
+                            position: format!("track {}", number),
+                            message: "missing title".to_string(),
+                        });
+                    } else {
+                        playlist.tracks.push(source_track);
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    Ok(playlist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_playlist;
+    use crate::error::ErrorKind;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_xspf() {
+        let xspf = r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <title>Road Trip</title>
+  <trackList>
+    <track>
+      <location>file:///music/Queen/Bohemian%20Rhapsody.mp3</location>
+      <title>Bohemian Rhapsody</title>
+      <creator>Queen</creator>
+      <album>A Night at the Opera</album>
+      <duration>354320</duration>
+      <extension application="http://www.videolan.org/vlc/playlist/0">
+        <vlc:id>0</vlc:id>
+      </extension>
+    </track>
+    <track>
+      <location>file:///music/Simon%20%26%20Garfunkel%20-%20The%20Boxer.flac</location>
+    </track>
+    <track>
+      <identifier>https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC</identifier>
+    </track>
+    <track>
+      <creator>Nobody</creator>
+    </track>
+  </trackList>
+</playlist>"#;
+        let playlist = parse_playlist("/music/road-trip.xspf", xspf.as_bytes()).unwrap();
+        assert_eq!(playlist.name.as_deref(), Some("Road Trip"));
+        assert_eq!(playlist.tracks.len(), 3);
+        let track = &playlist.tracks[0];
+        assert_eq!(track.name, "Bohemian Rhapsody");
+        assert_eq!(track.artist.as_deref(), Some("Queen"));
+        assert_eq!(track.album.as_deref(), Some("A Night at the Opera"));
+        assert_eq!(track.duration, Some(354_320));
+        assert_eq!(
+            track.path,
+            Some(PathBuf::from("/music/Queen/Bohemian Rhapsody.mp3"))
+        );
+        assert_eq!(playlist.tracks[1].name, "The Boxer");
+        assert_eq!(
+            playlist.tracks[1].artist.as_deref(),
+            Some("Simon & Garfunkel")
+        );
+        assert_eq!(
+            playlist.tracks[2].spotify_uri.as_deref(),
+            Some("spotify:track:4uLU6hMCjMI75M1A2tKUQC")
+        );
+        assert_eq!(playlist.tracks[2].path, None);
+        assert_eq!(playlist.warnings[0].to_string(), "track 4: missing title");
+    }
+
+    #[test]
+    fn test_malformed_xspf() {
+        let error = parse_playlist("broken.xspf", b"<playlist><trackList></playlist>").unwrap_err();
+        match error.kind {
+            ErrorKind::Parse { .. } => (),
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+}
@@ -1,15 +1,38 @@
+use crate::dedupe::Occurrences;
 use crate::error::{Error, ErrorKind};
+use crate::limiter::TokenBucket;
+use crate::playlist::Visibility;
+use crate::progress;
 use crate::types;
-use failure;
+use base64::Engine;
 use rspotify::spotify::client::{ApiError, Spotify};
+use rspotify::spotify::model::album::SimplifiedAlbum;
+use rspotify::spotify::model::artist::FullArtist;
+use rspotify::spotify::model::cud_result::CUDResult;
 use rspotify::spotify::model::playlist::{FullPlaylist, SimplifiedPlaylist};
-use rspotify::spotify::model::track::FullTrack;
+use rspotify::spotify::model::track::{FullTrack, SimplifiedTrack};
 use rspotify::spotify::oauth2::SpotifyClientCredentials;
 use rspotify::spotify::oauth2::SpotifyOAuth;
 use rspotify::spotify::oauth2::TokenInfo;
 use rspotify::spotify::util::get_token;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
 
 const SPOTIFY_CLIENT_REDIRECT_URI: &str = "http://localhost:8080/";
+/// Number of times a rate limited API call is retried before giving up.
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+/// Most items the Spotify API returns for a page of album tracks, or looks up or saves at once.
+const MAX_PAGE_SIZE: usize = 50;
+/// Most albums the Spotify API saves to the user's library at once.
+const MAX_SAVED_ALBUMS: usize = 20;
+/// Most tracks listed, added or removed by one playlist request.
+const MAX_PLAYLIST_PAGE_SIZE: usize = 100;
+/// Average number of API requests per second, shared by all threads. Spotify doesn't publish its
+/// limit, so going over it is left to `Retry-After`.
+const REQUESTS_PER_SECOND: f64 = 10.0;
+/// Number of API requests that can be made at once after a quiet spell.
+const REQUEST_BURST: f64 = 20.0;
 
 /// Authenticate with the Spotify API and retrieve an API token.
 pub fn authenticate(
@@ -19,8 +42,12 @@ pub fn authenticate(
     let mut oauth = SpotifyOAuth::default()
         .client_id(spotify_client_id)
         .client_secret(spotify_client_secret)
-        .redirect_uri(&SPOTIFY_CLIENT_REDIRECT_URI)
-        .scope("user-read-recently-played playlist-read-private playlist-modify-private")
+        .redirect_uri(SPOTIFY_CLIENT_REDIRECT_URI)
+        .scope(
+            "user-read-recently-played playlist-read-private playlist-modify-private \
+             playlist-modify-public user-library-read user-library-modify user-follow-modify \
+             ugc-image-upload",
+        )
         .build();
 
     match get_token(&mut oauth) {
@@ -31,8 +58,62 @@ pub fn authenticate(
     }
 }
 
+/// A failed response to a request made without rspotify, which keeps the response body that
+/// rspotify's `ApiError` drops.
+#[derive(Debug)]
+struct ResponseError {
+    status: u16,
+    body: String,
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "status code {}: {}", self.status, self.body)
+    }
+}
+
+impl failure::Fail for ResponseError {}
+
+/// The response to a request for several tracks.
+#[derive(Deserialize)]
+struct Tracks {
+    tracks: Vec<Option<FullTrack>>,
+}
+
+/// Make a request without rspotify, authorized as rspotify's own requests are. A failed response
+/// becomes the error rspotify would return, keeping its body.
+fn send(
+    spotify: &Spotify,
+    request: reqwest::RequestBuilder,
+) -> Result<reqwest::Response, failure::Error> {
+    let token = match spotify.client_credentials_manager {
+        Some(ref credentials) => credentials.get_access_token(),
+        None => spotify.access_token.clone().unwrap_or_default(),
+    };
+    let mut response = request.bearer_auth(token).send()?;
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    match ApiError::from(&response) {
+        ApiError::Other(status) => Err(ResponseError {
+            status,
+            body: response.text().unwrap_or_default(),
+        }
+        .into()),
+        api_error => Err(api_error.into()),
+    }
+}
+
+/// The body of a request removing tracks at given positions from a playlist.
+#[derive(Serialize)]
+struct RemoveOccurrences<'a> {
+    tracks: &'a [Occurrences<'a>],
+    snapshot_id: &'a str,
+}
+
 pub struct SpotifyWrapper {
     client: Spotify,
+    limiter: TokenBucket,
 }
 
 impl SpotifyWrapper {
@@ -41,7 +122,7 @@ impl SpotifyWrapper {
         spotify_client_id: &str,
         spotify_client_secret: &str,
     ) -> Result<SpotifyWrapper, Error> {
-        let token = authenticate(&spotify_client_id, &spotify_client_secret)?;
+        let token = authenticate(spotify_client_id, spotify_client_secret)?;
         let credentials = SpotifyClientCredentials::default()
             .token_info(token)
             .build();
@@ -49,6 +130,7 @@ impl SpotifyWrapper {
             client: Spotify::default()
                 .client_credentials_manager(credentials)
                 .build(),
+            limiter: TokenBucket::new(REQUESTS_PER_SECOND, REQUEST_BURST),
         })
     }
 
@@ -82,9 +164,75 @@ impl SpotifyWrapper {
     }
 
     /// Create a user playlist
-    pub fn create_playlist(&self, name: &str) -> Result<FullPlaylist, Error> {
+    pub fn create_playlist(
+        &self,
+        name: &str,
+        visibility: Visibility,
+        description: Option<&str>,
+    ) -> Result<FullPlaylist, Error> {
         let user_id: String = self.user_id()?;
-        self.rate_limit_call(|spotify| spotify.user_playlist_create(&user_id, name, false, None))
+        let description = description.map(str::to_string);
+        let playlist = self.rate_limit_call(|spotify| {
+            spotify.user_playlist_create(
+                &user_id,
+                name,
+                visibility.is_public(),
+                description.clone(),
+            )
+        })?;
+        // Playlists can only be made collaborative once they exist.
+        if visibility.is_collaborative() {
+            self.rate_limit_call(|spotify| {
+                spotify.user_playlist_change_detail(
+                    &user_id,
+                    &playlist.id,
+                    None,
+                    None,
+                    None,
+                    Some(true),
+                )
+            })?;
+        }
+        Ok(playlist)
+    }
+
+    /// Replace the description of a playlist.
+    pub fn set_playlist_description(
+        &self,
+        playlist_id: &str,
+        description: &str,
+    ) -> Result<(), Error> {
+        let user_id: String = self.user_id()?;
+        self.rate_limit_call(|spotify| {
+            spotify.user_playlist_change_detail(
+                &user_id,
+                playlist_id,
+                None,
+                None,
+                Some(description.to_string()),
+                None,
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Replace the cover image of a playlist with a JPEG of at most 256KB once base64 encoded.
+    /// https://developer.spotify.com/documentation/web-api/reference/playlists/upload-custom-playlist-cover/
+    pub fn upload_playlist_cover(&self, playlist_id: &str, jpeg: &[u8]) -> Result<(), Error> {
+        // rspotify has no call for this endpoint, and its requests are always JSON.
+        let url = format!(
+            "https://api.spotify.com/v1/playlists/{}/images",
+            playlist_id
+        );
+        let body = base64::engine::general_purpose::STANDARD.encode(jpeg);
+        self.rate_limit_call(|spotify| {
+            let request = reqwest::Client::new()
+                .put(&url)
+                .header(reqwest::header::CONTENT_TYPE, "image/jpeg")
+                .body(body.clone());
+            send(spotify, request)?;
+            Ok(())
+        })
     }
 
     /// Attempt an exact track match otherwise error no or multiple results are found.
@@ -94,7 +242,7 @@ impl SpotifyWrapper {
         artist: Option<&str>,
         album: Option<&str>,
     ) -> Result<FullTrack, Error> {
-        let search_query = SpotifyWrapper::generate_search_query(&name, artist, album);
+        let search_query = SpotifyWrapper::generate_search_query(name, artist, album);
 
         let result =
             self.rate_limit_call(|spotify| spotify.search_track(&search_query, 1, 0, None))?;
@@ -109,6 +257,186 @@ impl SpotifyWrapper {
         Ok(page.items[0].clone())
     }
 
+    /// Find a track by its ISRC. Several releases can share a recording, so the first one is used.
+    pub fn isrc_match(&self, isrc: &str) -> Result<FullTrack, Error> {
+        let search_query = format!("isrc:{}", isrc);
+        let result =
+            self.rate_limit_call(|spotify| spotify.search_track(&search_query, 1, 0, None))?;
+        match result.tracks.items.into_iter().next() {
+            Some(track) => Ok(track),
+            None => Err(Error::new(ErrorKind::NoResults)),
+        }
+    }
+
+    /// Look up a track by its Spotify URI or ID.
+    pub fn track(&self, uri: &str) -> Result<FullTrack, Error> {
+        self.rate_limit_call(|spotify| spotify.track(uri))
+    }
+
+    /// Look up several tracks by their Spotify URIs or IDs, in the order given, with `None` for
+    /// tracks Spotify doesn't have (any more).
+    pub fn tracks(&self, uris: &[String]) -> Result<Vec<Option<FullTrack>>, Error> {
+        // rspotify can't read the `null` Spotify returns for a track it doesn't have.
+        let mut tracks = Vec::with_capacity(uris.len());
+        for chunk in uris.chunks(MAX_PAGE_SIZE) {
+            let ids: Vec<&str> = chunk
+                .iter()
+                .map(|uri| uri.rsplit(':').next().unwrap_or(uri))
+                .collect();
+            let ids = ids.join(",");
+            let result: Tracks = self.rate_limit_call(|spotify| {
+                let request = reqwest::Client::new()
+                    .get("https://api.spotify.com/v1/tracks")
+                    .query(&[("ids", &ids)]);
+                Ok(send(spotify, request)?.json()?)
+            })?;
+            // Every track has its place in the response, so a short one can't be matched up.
+            if result.tracks.len() != chunk.len() {
+                return Err(Error::new(ErrorKind::Network(format!(
+                    "Spotify returned {} tracks for {} IDs",
+                    result.tracks.len(),
+                    chunk.len()
+                ))));
+            }
+            tracks.extend(result.tracks);
+        }
+        Ok(tracks)
+    }
+
+    /// Save tracks to the user's Liked Songs, skipping tracks that are already saved so they keep
+    /// the date they were first saved. Returns the number of tracks saved.
+    pub fn save_tracks(&self, uris: &[String]) -> Result<usize, Error> {
+        let mut saved = 0;
+        for chunk in uris.chunks(MAX_PAGE_SIZE) {
+            let contains =
+                self.rate_limit_call(|spotify| spotify.current_user_saved_tracks_contains(chunk))?;
+            let unsaved: Vec<String> = chunk
+                .iter()
+                .zip(contains)
+                .filter(|&(_, contained)| !contained)
+                .map(|(uri, _)| uri.clone())
+                .collect();
+            if unsaved.is_empty() {
+                continue;
+            }
+            self.rate_limit_call(|spotify| spotify.current_user_saved_tracks_add(&unsaved))?;
+            saved += unsaved.len();
+        }
+        Ok(saved)
+    }
+
+    /// Save albums to the user's library.
+    pub fn save_albums(&self, ids: &[String]) -> Result<(), Error> {
+        for chunk in ids.chunks(MAX_SAVED_ALBUMS) {
+            self.rate_limit_call(|spotify| spotify.current_user_saved_albums_add(chunk))?;
+        }
+        Ok(())
+    }
+
+    /// Search albums by name and artist, most relevant first.
+    pub fn search_albums(
+        &self,
+        name: &str,
+        artist: &str,
+        limit: u32,
+    ) -> Result<Vec<SimplifiedAlbum>, Error> {
+        let search_query = format!("album:{} artist:{}", name, artist);
+        let result =
+            self.rate_limit_call(|spotify| spotify.search_album(&search_query, limit, 0, None))?;
+        Ok(result.albums.items)
+    }
+
+    /// Search artists by name, most relevant first.
+    pub fn search_artists(&self, name: &str, limit: u32) -> Result<Vec<FullArtist>, Error> {
+        let search_query = format!("artist:{}", name);
+        let result =
+            self.rate_limit_call(|spotify| spotify.search_artist(&search_query, limit, 0, None))?;
+        Ok(result.artists.items)
+    }
+
+    /// Follow artists by their Spotify IDs. Following an artist twice has no effect.
+    pub fn follow_artists(&self, ids: &[String]) -> Result<(), Error> {
+        for chunk in ids.chunks(MAX_PAGE_SIZE) {
+            self.rate_limit_call(|spotify| spotify.user_follow_artists(chunk))?;
+        }
+        Ok(())
+    }
+
+    /// List the tracks of an album, in disc and track order.
+    pub fn album_tracks(&self, album_id: &str) -> Result<Vec<SimplifiedTrack>, Error> {
+        let mut tracks = Vec::new();
+        loop {
+            let offset = tracks.len() as u32;
+            let page = self.rate_limit_call(|spotify| {
+                spotify.album_track(album_id, MAX_PAGE_SIZE as u32, offset)
+            })?;
+            let done = page.next.is_none() || page.items.is_empty();
+            tracks.extend(page.items);
+            if done {
+                return Ok(tracks);
+            }
+        }
+    }
+
+    /// List the tracks of a playlist, in playlist order.
+    pub fn playlist_tracks(&self, playlist_id: &str) -> Result<Vec<FullTrack>, Error> {
+        let user_id: String = self.user_id()?;
+        let mut tracks = Vec::new();
+        loop {
+            let offset = tracks.len() as u32;
+            let page = self.rate_limit_call(|spotify| {
+                spotify.user_playlist_tracks(
+                    &user_id,
+                    playlist_id,
+                    None,
+                    MAX_PLAYLIST_PAGE_SIZE as u32,
+                    offset,
+                    None,
+                )
+            })?;
+            let done = page.next.is_none() || page.items.is_empty();
+            tracks.extend(page.items.into_iter().map(|item| item.track));
+            if done {
+                return Ok(tracks);
+            }
+        }
+    }
+
+    /// The snapshot ID of the current version of a playlist.
+    pub fn playlist_snapshot_id(&self, playlist_id: &str) -> Result<String, Error> {
+        let user_id: String = self.user_id()?;
+        let playlist = self.rate_limit_call(|spotify| {
+            spotify.user_playlist(&user_id, Some(&mut playlist_id.to_string()), None, None)
+        })?;
+        Ok(playlist.snapshot_id)
+    }
+
+    /// Remove tracks at the given positions from the version `snapshot_id` of a playlist, at most
+    /// 100 positions at once. Returns the snapshot ID of the playlist without them.
+    /// https://developer.spotify.com/documentation/web-api/reference/playlists/remove-tracks-playlist/
+    pub fn remove_playlist_occurrences(
+        &self,
+        playlist_id: &str,
+        occurrences: &[Occurrences],
+        snapshot_id: &str,
+    ) -> Result<String, Error> {
+        // rspotify sends the positions as `position`, which Spotify ignores and then removes
+        // every occurrence of the tracks.
+        let url = format!(
+            "https://api.spotify.com/v1/playlists/{}/tracks",
+            playlist_id
+        );
+        let body = RemoveOccurrences {
+            tracks: occurrences,
+            snapshot_id,
+        };
+        let result: CUDResult = self.rate_limit_call(|spotify| {
+            let request = reqwest::Client::new().delete(&url).json(&body);
+            Ok(send(spotify, request)?.json()?)
+        })?;
+        Ok(result.snapshot_id)
+    }
+
     /// List the user's playlists.
     pub fn list_playlists(&self) -> Result<Vec<SimplifiedPlaylist>, Error> {
         let mut playlists: Vec<SimplifiedPlaylist> = Vec::new();
@@ -128,33 +456,65 @@ impl SpotifyWrapper {
         Ok(playlists)
     }
 
+    /// The display name of the user, or their user id if they have none.
+    pub fn profile_name(&self) -> Result<String, Error> {
+        let user = self.rate_limit_call(|spotify| spotify.me())?;
+        Ok(user.display_name.unwrap_or(user.id))
+    }
+
     /// Return the user id from the access token
     fn user_id(&self) -> Result<String, Error> {
         Ok(self.rate_limit_call(|spotify| spotify.me())?.id)
     }
 
+    /// Call the Spotify API, sleeping and retrying whenever the API reports a rate limit.
+    ///
+    /// Calls from all threads share the request rate, and a rate limit pauses them all.
     fn rate_limit_call<F, R>(&self, func: F) -> Result<R, Error>
     where
         F: Fn(&Spotify) -> Result<R, failure::Error>,
     {
-        match func(&self.client) {
-            Ok(v) => Ok(v),
-            Err(e) => match e
-                .downcast::<ApiError>()
-                .expect("unable to handle spotify error")
-            {
-                ApiError::Unauthorized => Err(Error::new(ErrorKind::Unauthorized)),
+        let mut retries = 0;
+        loop {
+            self.limiter.acquire();
+            let error = match func(&self.client) {
+                Ok(v) => return Ok(v),
+                Err(e) => e,
+            };
+            let error = match error.downcast::<ResponseError>() {
+                Ok(ResponseError { status, body }) => {
+                    let body = Some(body).filter(|body| !body.is_empty());
+                    return Err(Error::new(ErrorKind::ApiStatus { status, body }));
+                }
+                Err(e) => e,
+            };
+            let api_error = match error.downcast::<ApiError>() {
+                Ok(api_error) => api_error,
+                Err(e) => {
+                    return Err(Error::with_source(
+                        ErrorKind::Network("request to the Spotify API failed".to_string()),
+                        e.compat(),
+                    ))
+                }
+            };
+            match api_error {
+                ApiError::Unauthorized => return Err(Error::new(ErrorKind::Unauthorized)),
+                ApiError::RateLimited(_) if retries >= MAX_RATE_LIMIT_RETRIES => {
+                    return Err(Error::new(ErrorKind::RateLimitExhausted(retries)))
+                }
                 ApiError::RateLimited(d) => {
                     let duration = d.unwrap_or(10);
                     warn!("spotify rate limit hit. sleeping for {} seconds", duration);
-                    std::thread::sleep(std::time::Duration::from_secs(duration as u64));
-                    self.rate_limit_call(func)
+                    let duration = Duration::from_secs(duration as u64);
+                    self.limiter.pause(duration);
+                    progress::rate_limit_sleep(duration);
+                    retries += 1;
                 }
-                ApiError::Other(status_code) => panic!(
-                    "api called to spotify failed with status code {}",
-                    status_code
-                ),
-            },
+                // rspotify drops the body of failed responses to its own requests.
+                ApiError::Other(status) => {
+                    return Err(Error::new(ErrorKind::ApiStatus { status, body: None }))
+                }
+            }
         }
     }
 }
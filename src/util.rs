@@ -1,6 +1,58 @@
+use crate::error::{Error, ErrorKind};
+use crate::itunes::STDIN_PATH;
 use chrono::prelude::*;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
 
 pub fn datetime_to_string() -> String {
     let local_time: DateTime<Local> = Local::now();
     local_time.format("%Y-%m-%d %H:%M:%S").to_string()
 }
+
+/// The file name of a path without its extensions, e.g. `Road Trip` for `/tmp/Road Trip.xml.gz`.
+pub fn file_stem(path: &str) -> String {
+    let name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match name.find('.') {
+        Some(0) | None => name,
+        Some(i) => name[..i].to_string(),
+    }
+}
+
+/// Check that a playlist file exists and isn't a directory.
+pub fn check_input_file(path: &str) -> Result<(), Error> {
+    let p = Path::new(path);
+    if !p.exists() {
+        return Err(Error::new(ErrorKind::FileNotFound(format!(
+            "Can't find playlist file {}",
+            path
+        ))));
+    }
+    if !p.is_file() {
+        return Err(Error::new(ErrorKind::NotAFile(format!(
+            "Expected a playlist file, found a directory at {}",
+            path
+        ))));
+    }
+    Ok(())
+}
+
+/// Read a whole playlist file, or stdin if `path` is `-`.
+pub fn read_input(path: &str) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    if path == STDIN_PATH {
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|e| Error::with_source(ErrorKind::Io("stdin".to_string()), e))?;
+        return Ok(bytes);
+    }
+
+    check_input_file(path)?;
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|e| Error::with_source(ErrorKind::Io(path.to_string()), e))?;
+    Ok(bytes)
+}